@@ -0,0 +1,328 @@
+use crate::errors::{err, Result, StatusCode};
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const U32_SIZE: usize = 4;
+const U64_SIZE: usize = 8;
+/// Every record starts with the key length and the value length.
+const RECORD_HEADER_SIZE: u64 = 2 * U32_SIZE as u64;
+
+/// BlobIndex is stored in the LSM tree (as a `ValueType::TypeBlobIndex` entry) in place of a
+/// value that was moved into a blob file. It points at the value within that file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlobIndex {
+    pub file_number: u64,
+    /// offset of the value (not the record) within the blob file.
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl BlobIndex {
+    pub const ENCODED_SIZE: usize = 3 * U64_SIZE;
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(Self::ENCODED_SIZE);
+        v.extend_from_slice(&self.file_number.to_le_bytes());
+        v.extend_from_slice(&self.offset.to_le_bytes());
+        v.extend_from_slice(&self.size.to_le_bytes());
+        v
+    }
+
+    pub fn decode(src: &[u8]) -> Result<BlobIndex> {
+        if src.len() != Self::ENCODED_SIZE {
+            return err(
+                StatusCode::Corruption,
+                format!("blob index has bad length {}", src.len()),
+            );
+        }
+        let u64_at = |i: usize| {
+            let mut b = [0; U64_SIZE];
+            b.copy_from_slice(&src[i * U64_SIZE..(i + 1) * U64_SIZE]);
+            u64::from_le_bytes(b)
+        };
+        Ok(BlobIndex {
+            file_number: u64_at(0),
+            offset: u64_at(1),
+            size: u64_at(2),
+        })
+    }
+}
+
+/// BlobFileBuilder appends records to a blob file. A record is laid out as
+///
+/// [key_len: u32][value_len: u32][key][value]
+///
+/// The key is kept next to the value so that garbage collection can check whether the LSM tree
+/// still refers to a record.
+pub struct BlobFileBuilder<W: Write> {
+    file_number: u64,
+    dst: W,
+    offset: u64,
+    num_records: usize,
+}
+
+impl<W: Write> BlobFileBuilder<W> {
+    pub fn new(file_number: u64, dst: W) -> BlobFileBuilder<W> {
+        BlobFileBuilder {
+            file_number,
+            dst,
+            offset: 0,
+            num_records: 0,
+        }
+    }
+
+    /// Appends a record and returns the index to store in the LSM tree instead of the value.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<BlobIndex> {
+        let key_len = record_len("key", key.len())?;
+        let value_len = record_len("value", value.len())?;
+        self.dst.write_all(&key_len.to_le_bytes())?;
+        self.dst.write_all(&value_len.to_le_bytes())?;
+        self.dst.write_all(key)?;
+        self.dst.write_all(value)?;
+
+        let index = BlobIndex {
+            file_number: self.file_number,
+            offset: self.offset + RECORD_HEADER_SIZE + key.len() as u64,
+            size: value.len() as u64,
+        };
+        self.offset = index.offset + index.size;
+        self.num_records += 1;
+        Ok(index)
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn num_records(&self) -> usize {
+        self.num_records
+    }
+
+    /// Flushes the file and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.dst.flush()?;
+        Ok(self.dst)
+    }
+}
+
+/// Returns `len` as stored in a record header, or an error if it doesn't fit.
+fn record_len(what: &str, len: usize) -> Result<u32> {
+    u32::try_from(len).or_else(|_| {
+        err(
+            StatusCode::InvalidArgument,
+            format!("blob {} of {} bytes is too large", what, len),
+        )
+    })
+}
+
+/// BlobFileReader reads values from a blob file written by a `BlobFileBuilder`.
+pub struct BlobFileReader<R: Read + Seek> {
+    file_number: u64,
+    src: R,
+}
+
+impl<R: Read + Seek> BlobFileReader<R> {
+    pub fn new(file_number: u64, src: R) -> BlobFileReader<R> {
+        BlobFileReader { file_number, src }
+    }
+
+    /// Reads the value `index` points to.
+    pub fn get(&mut self, index: &BlobIndex) -> Result<Vec<u8>> {
+        if index.file_number != self.file_number {
+            return err(
+                StatusCode::InvalidArgument,
+                format!(
+                    "blob index for file {} used with file {}",
+                    index.file_number, self.file_number
+                ),
+            );
+        }
+        let len = self.src.seek(SeekFrom::End(0))?;
+        let end = index.offset.checked_add(index.size);
+        if end.is_none_or(|end| end > len) {
+            return err(
+                StatusCode::Corruption,
+                format!(
+                    "blob index (offset {}, size {}) points past the end of file {}",
+                    index.offset, index.size, self.file_number
+                ),
+            );
+        }
+        self.src.seek(SeekFrom::Start(index.offset))?;
+        let mut value = vec![0; index.size as usize];
+        self.src.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    /// Returns the key and index of every record in the file, in the order they were written.
+    pub fn records(&mut self) -> Result<Vec<(Vec<u8>, BlobIndex)>> {
+        let len = self.src.seek(SeekFrom::End(0))?;
+        self.src.seek(SeekFrom::Start(0))?;
+
+        let mut records = vec![];
+        let mut offset = 0;
+        while offset < len {
+            if len - offset < RECORD_HEADER_SIZE {
+                return err(
+                    StatusCode::Corruption,
+                    format!("truncated blob record header at offset {}", offset),
+                );
+            }
+            let mut header = [0; RECORD_HEADER_SIZE as usize];
+            self.src.read_exact(&mut header)?;
+            let key_len = u32::from_le_bytes(header[..U32_SIZE].try_into().unwrap()) as u64;
+            let value_len = u32::from_le_bytes(header[U32_SIZE..].try_into().unwrap()) as u64;
+
+            let value_offset = offset + RECORD_HEADER_SIZE + key_len;
+            if value_offset + value_len > len {
+                return err(
+                    StatusCode::Corruption,
+                    format!("truncated blob record at offset {}", offset),
+                );
+            }
+            let mut key = vec![0; key_len as usize];
+            self.src.read_exact(&mut key)?;
+            self.src.seek(SeekFrom::Current(value_len as i64))?;
+
+            records.push((
+                key,
+                BlobIndex {
+                    file_number: self.file_number,
+                    offset: value_offset,
+                    size: value_len,
+                },
+            ));
+            offset = value_offset + value_len;
+        }
+        Ok(records)
+    }
+}
+
+/// Garbage-collects a blob file: every record for which `is_live(key, index)` holds -- i.e. the
+/// LSM tree still refers to it -- is copied to `dst`. Returns the keys of the copied records with
+/// their new indices, which have to replace the old indices in the LSM tree before the old file can
+/// be deleted.
+pub fn rewrite_live_blobs<R, W, F>(
+    src: &mut BlobFileReader<R>,
+    dst: &mut BlobFileBuilder<W>,
+    mut is_live: F,
+) -> Result<Vec<(Vec<u8>, BlobIndex)>>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(&[u8], &BlobIndex) -> bool,
+{
+    let mut moved = vec![];
+    for (key, index) in src.records()? {
+        if !is_live(&key, &index) {
+            continue;
+        }
+        let value = src.get(&index)?;
+        let new_index = dst.add(&key, &value)?;
+        moved.push((key, new_index));
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn make_blob_file() -> (Vec<u8>, Vec<BlobIndex>) {
+        let mut b = BlobFileBuilder::new(7, vec![]);
+        let indices = vec![
+            b.add(b"abc", b"value1").unwrap(),
+            b.add(b"abd", &[0xab; 1000]).unwrap(),
+            b.add(b"abe", b"").unwrap(),
+        ];
+        assert_eq!(b.num_records(), 3);
+        assert_eq!(b.file_size(), 3 * 8 + 9 + 6 + 1000);
+        (b.finish().unwrap(), indices)
+    }
+
+    #[test]
+    fn test_blob_index_encoding() {
+        let idx = BlobIndex {
+            file_number: 12,
+            offset: 1 << 40,
+            size: 4096,
+        };
+        let enc = idx.encode();
+        assert_eq!(enc.len(), BlobIndex::ENCODED_SIZE);
+        assert_eq!(BlobIndex::decode(&enc).unwrap(), idx);
+        assert_eq!(
+            BlobIndex::decode(&enc[1..]).unwrap_err().code,
+            StatusCode::Corruption
+        );
+    }
+
+    #[test]
+    fn test_blob_file_read_write() {
+        let (file, indices) = make_blob_file();
+        let mut r = BlobFileReader::new(7, Cursor::new(file));
+
+        assert_eq!(r.get(&indices[0]).unwrap(), b"value1");
+        assert_eq!(r.get(&indices[1]).unwrap(), vec![0xab; 1000]);
+        assert_eq!(r.get(&indices[2]).unwrap(), b"");
+
+        let records = r.records().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], (b"abd".to_vec(), indices[1].clone()));
+
+        let mut wrong_file = indices[0].clone();
+        wrong_file.file_number = 8;
+        assert_eq!(
+            r.get(&wrong_file).unwrap_err().code,
+            StatusCode::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_blob_file_truncated() {
+        let (mut file, _) = make_blob_file();
+        file.truncate(file.len() - 1);
+        let mut r = BlobFileReader::new(7, Cursor::new(file));
+        assert_eq!(r.records().unwrap_err().code, StatusCode::Corruption);
+    }
+
+    #[test]
+    fn test_blob_record_too_large() {
+        assert_eq!(record_len("value", u32::MAX as usize).unwrap(), u32::MAX);
+        assert_eq!(
+            record_len("value", u32::MAX as usize + 1).unwrap_err().code,
+            StatusCode::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_blob_file_corrupt_index() {
+        let (file, indices) = make_blob_file();
+        let mut r = BlobFileReader::new(7, Cursor::new(file));
+
+        let mut too_large = indices[0].clone();
+        too_large.size = u64::MAX - 1;
+        assert_eq!(r.get(&too_large).unwrap_err().code, StatusCode::Corruption);
+
+        let mut past_end = indices[2].clone();
+        past_end.offset += 1;
+        assert_eq!(r.get(&past_end).unwrap_err().code, StatusCode::Corruption);
+    }
+
+    #[test]
+    fn test_rewrite_live_blobs() {
+        let (file, _) = make_blob_file();
+        let mut src = BlobFileReader::new(7, Cursor::new(file));
+        let mut dst = BlobFileBuilder::new(9, vec![]);
+
+        let moved = rewrite_live_blobs(&mut src, &mut dst, |k, _| k != b"abd").unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0].0, b"abc");
+        assert_eq!(moved[1].0, b"abe");
+        assert_eq!(moved[0].1.file_number, 9);
+
+        let mut r = BlobFileReader::new(9, Cursor::new(dst.finish().unwrap()));
+        assert_eq!(r.get(&moved[0].1).unwrap(), b"value1");
+        assert_eq!(r.records().unwrap().len(), 2);
+    }
+}
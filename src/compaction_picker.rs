@@ -0,0 +1,391 @@
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompactionStyle {
+    /// Files are organized in levels of exponentially growing size, each level (but L0) being a
+    /// single sorted run.
+    Level,
+    /// Files are organized in sorted runs of similar size that are merged as a whole; this trades
+    /// read and space amplification for lower write amplification.
+    Universal,
+    /// All files stay in L0 and are never merged; the oldest files are deleted once the total
+    /// size or their age exceeds a limit. Only suitable for data that expires in insertion order.
+    Fifo,
+}
+
+/// A sorted run is either a single L0 file or a complete level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortedRun {
+    pub level: usize,
+    pub size: u64,
+    pub being_compacted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompactionReason {
+    /// The runs newer than the oldest one take up too much space compared to it.
+    SizeAmplification,
+    /// Neighbouring runs of similar size are merged.
+    SizeRatio,
+    /// There are more sorted runs than the compaction trigger allows.
+    SortedRunNum,
+    /// FIFO: files older than the TTL are dropped.
+    FifoTtl,
+    /// FIFO: the oldest files are dropped to get under the size limit.
+    FifoMaxTableFilesSize,
+}
+
+/// A compaction chosen by a picker: the sorted runs in `inputs` (indices into the runs passed to
+/// the picker) are merged into `output_level`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickedCompaction {
+    pub inputs: Range<usize>,
+    pub output_level: usize,
+    pub reason: CompactionReason,
+}
+
+#[derive(Clone, Debug)]
+pub struct UniversalCompactionOptions {
+    /// Percentage by which a run may be larger than the accumulated size of the newer runs it's
+    /// merged with.
+    pub size_ratio: u64,
+    pub min_merge_width: usize,
+    pub max_merge_width: usize,
+    /// Maximum size of all runs but the oldest, as a percentage of the oldest run's size, before
+    /// all runs are compacted together.
+    pub max_size_amplification_percent: u64,
+}
+
+impl Default for UniversalCompactionOptions {
+    fn default() -> Self {
+        UniversalCompactionOptions {
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: usize::MAX,
+            max_size_amplification_percent: 200,
+        }
+    }
+}
+
+/// UniversalCompactionPicker chooses which sorted runs to merge under `CompactionStyle::Universal`.
+pub struct UniversalCompactionPicker {
+    opts: UniversalCompactionOptions,
+    level0_file_num_compaction_trigger: usize,
+}
+
+impl UniversalCompactionPicker {
+    pub fn new(
+        opts: UniversalCompactionOptions,
+        level0_file_num_compaction_trigger: usize,
+    ) -> UniversalCompactionPicker {
+        UniversalCompactionPicker {
+            opts,
+            level0_file_num_compaction_trigger,
+        }
+    }
+
+    /// Picks a compaction from `runs`, which are ordered from newest to oldest. Returns `None` if
+    /// there are fewer runs than the compaction trigger, or if nothing is worth compacting.
+    pub fn pick(&self, runs: &[SortedRun]) -> Option<PickedCompaction> {
+        if runs.len() < self.level0_file_num_compaction_trigger.max(2) {
+            return None;
+        }
+        self.pick_size_amplification(runs)
+            .or_else(|| self.pick_size_ratio(runs))
+            .or_else(|| self.pick_sorted_run_num(runs))
+    }
+
+    fn picked(
+        &self,
+        runs: &[SortedRun],
+        inputs: Range<usize>,
+        reason: CompactionReason,
+    ) -> Option<PickedCompaction> {
+        if runs[inputs.clone()].iter().any(|r| r.being_compacted) {
+            return None;
+        }
+        Some(PickedCompaction {
+            output_level: runs[inputs.end - 1].level,
+            inputs,
+            reason,
+        })
+    }
+
+    fn pick_size_amplification(&self, runs: &[SortedRun]) -> Option<PickedCompaction> {
+        let (oldest, newer) = runs.split_last()?;
+        let newer_size: u64 = newer.iter().map(|r| r.size).sum();
+        if newer_size as u128 * 100
+            < oldest.size as u128 * self.opts.max_size_amplification_percent as u128
+        {
+            return None;
+        }
+        self.picked(runs, 0..runs.len(), CompactionReason::SizeAmplification)
+    }
+
+    fn pick_size_ratio(&self, runs: &[SortedRun]) -> Option<PickedCompaction> {
+        let max_width = self.opts.max_merge_width.max(self.opts.min_merge_width);
+
+        for start in 0..runs.len() {
+            if runs[start].being_compacted {
+                continue;
+            }
+            let mut candidate_size = runs[start].size;
+            let mut end = start + 1;
+            while end < runs.len() && end - start < max_width {
+                let next = &runs[end];
+                if next.being_compacted
+                    || (candidate_size as u128 * (100 + self.opts.size_ratio) as u128 / 100)
+                        < next.size as u128
+                {
+                    break;
+                }
+                candidate_size += next.size;
+                end += 1;
+            }
+            if end - start >= self.opts.min_merge_width {
+                return self.picked(runs, start..end, CompactionReason::SizeRatio);
+            }
+        }
+        None
+    }
+
+    fn pick_sorted_run_num(&self, runs: &[SortedRun]) -> Option<PickedCompaction> {
+        if runs.len() <= self.level0_file_num_compaction_trigger {
+            return None;
+        }
+        // Merge just enough of the newest runs to get back to the trigger.
+        let width = (runs.len() - self.level0_file_num_compaction_trigger + 1)
+            .max(self.opts.min_merge_width)
+            .min(runs.len());
+        self.picked(runs, 0..width, CompactionReason::SortedRunNum)
+    }
+}
+
+/// An L0 file as seen by the FIFO compaction picker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FifoFile {
+    pub file_number: u64,
+    pub size: u64,
+    /// Seconds since the epoch, or 0 if unknown.
+    pub creation_time: u64,
+    pub being_compacted: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct FifoCompactionOptions {
+    /// Once all files together are larger than this, the oldest ones are deleted.
+    pub max_table_files_size: u64,
+    /// Files older than this many seconds are deleted. 0 disables the TTL.
+    pub ttl: u64,
+}
+
+impl Default for FifoCompactionOptions {
+    fn default() -> Self {
+        FifoCompactionOptions {
+            max_table_files_size: 1 << 30,
+            ttl: 0,
+        }
+    }
+}
+
+/// Files chosen for deletion by the FIFO picker, as indices into the files passed to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickedDeletion {
+    pub files: Vec<usize>,
+    pub reason: CompactionReason,
+}
+
+/// FifoCompactionPicker chooses which files to drop under `CompactionStyle::Fifo`. It never
+/// merges files.
+pub struct FifoCompactionPicker {
+    opts: FifoCompactionOptions,
+}
+
+impl FifoCompactionPicker {
+    pub fn new(opts: FifoCompactionOptions) -> FifoCompactionPicker {
+        FifoCompactionPicker { opts }
+    }
+
+    /// Picks the files to delete from `files`, which are ordered from newest to oldest, at time
+    /// `now` (seconds since the epoch). Expired files are dropped first; only if none are, the
+    /// size limit is enforced. Deletion always proceeds from the oldest file and stops at the first
+    /// file that is being compacted.
+    pub fn pick(&self, files: &[FifoFile], now: u64) -> Option<PickedDeletion> {
+        self.pick_ttl(files, now).or_else(|| self.pick_size(files))
+    }
+
+    fn pick_ttl(&self, files: &[FifoFile], now: u64) -> Option<PickedDeletion> {
+        if self.opts.ttl == 0 {
+            return None;
+        }
+        let expired: Vec<usize> = (0..files.len())
+            .rev()
+            .take_while(|i| {
+                let f = &files[*i];
+                // A file of unknown age is never considered expired.
+                !f.being_compacted
+                    && f.creation_time != 0
+                    && f.creation_time.saturating_add(self.opts.ttl) <= now
+            })
+            .collect();
+        if expired.is_empty() {
+            return None;
+        }
+        Some(PickedDeletion {
+            files: expired,
+            reason: CompactionReason::FifoTtl,
+        })
+    }
+
+    fn pick_size(&self, files: &[FifoFile]) -> Option<PickedDeletion> {
+        let mut total: u64 = files.iter().map(|f| f.size).sum();
+        let mut picked = vec![];
+        for i in (0..files.len()).rev() {
+            if total <= self.opts.max_table_files_size || files[i].being_compacted {
+                break;
+            }
+            total -= files[i].size;
+            picked.push(i);
+        }
+        if picked.is_empty() {
+            return None;
+        }
+        Some(PickedDeletion {
+            files: picked,
+            reason: CompactionReason::FifoMaxTableFilesSize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(sizes: &[u64]) -> Vec<SortedRun> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, s)| SortedRun {
+                level: if i == sizes.len() - 1 { 6 } else { 0 },
+                size: *s,
+                being_compacted: false,
+            })
+            .collect()
+    }
+
+    fn picker() -> UniversalCompactionPicker {
+        UniversalCompactionPicker::new(UniversalCompactionOptions::default(), 4)
+    }
+
+    #[test]
+    fn test_universal_below_trigger() {
+        assert_eq!(picker().pick(&runs(&[1, 1, 1])), None);
+    }
+
+    #[test]
+    fn test_universal_size_amplification() {
+        let picked = picker().pick(&runs(&[10, 10, 20, 20])).unwrap();
+        assert_eq!(picked.inputs, 0..4);
+        assert_eq!(picked.output_level, 6);
+        assert_eq!(picked.reason, CompactionReason::SizeAmplification);
+    }
+
+    #[test]
+    fn test_universal_size_ratio() {
+        // 1 + 1 = 2 >= 2, 2 + 2 = 4 < 10
+        let picked = picker().pick(&runs(&[1, 1, 2, 10, 1000])).unwrap();
+        assert_eq!(picked.inputs, 0..3);
+        assert_eq!(picked.output_level, 0);
+        assert_eq!(picked.reason, CompactionReason::SizeRatio);
+
+        // a run that is being compacted can't be part of the merge.
+        let mut r = runs(&[1, 1, 1, 1, 1000]);
+        r[1].being_compacted = true;
+        let picked = picker().pick(&r).unwrap();
+        assert_eq!(picked.inputs, 2..4);
+        assert_eq!(picked.reason, CompactionReason::SizeRatio);
+    }
+
+    #[test]
+    fn test_universal_sorted_run_num() {
+        // growing sizes never satisfy the size ratio.
+        let picked = picker().pick(&runs(&[1, 3, 9, 27, 81, 1000])).unwrap();
+        assert_eq!(picked.inputs, 0..3);
+        assert_eq!(picked.reason, CompactionReason::SortedRunNum);
+
+        assert_eq!(picker().pick(&runs(&[1, 3, 9, 1000])), None);
+    }
+
+    fn fifo_files(sizes_and_times: &[(u64, u64)]) -> Vec<FifoFile> {
+        sizes_and_times
+            .iter()
+            .enumerate()
+            .map(|(i, (size, time))| FifoFile {
+                file_number: (sizes_and_times.len() - i) as u64,
+                size: *size,
+                creation_time: *time,
+                being_compacted: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fifo_max_size() {
+        let picker = FifoCompactionPicker::new(FifoCompactionOptions {
+            max_table_files_size: 100,
+            ttl: 0,
+        });
+        assert_eq!(picker.pick(&fifo_files(&[(50, 0), (50, 0)]), 0), None);
+
+        let files = fifo_files(&[(50, 0), (40, 0), (30, 0), (20, 0)]);
+        assert_eq!(
+            picker.pick(&files, 0),
+            Some(PickedDeletion {
+                files: vec![3, 2],
+                reason: CompactionReason::FifoMaxTableFilesSize,
+            })
+        );
+
+        let mut files = files;
+        files[2].being_compacted = true;
+        assert_eq!(picker.pick(&files, 0).unwrap().files, vec![3]);
+    }
+
+    #[test]
+    fn test_fifo_ttl() {
+        let picker = FifoCompactionPicker::new(FifoCompactionOptions {
+            max_table_files_size: 1000,
+            ttl: 100,
+        });
+        let files = fifo_files(&[(1, 300), (1, 200), (1, 150), (1, 100)]);
+        assert_eq!(picker.pick(&files, 199), None);
+        assert_eq!(
+            picker.pick(&files, 250),
+            Some(PickedDeletion {
+                files: vec![3, 2],
+                reason: CompactionReason::FifoTtl,
+            })
+        );
+
+        // the TTL takes precedence over the size limit.
+        let picker = FifoCompactionPicker::new(FifoCompactionOptions {
+            max_table_files_size: 1,
+            ttl: 100,
+        });
+        assert_eq!(picker.pick(&files, 200).unwrap().files, vec![3]);
+        assert_eq!(
+            picker.pick(&files, 0).unwrap().reason,
+            CompactionReason::FifoMaxTableFilesSize
+        );
+
+        // files of unknown age don't expire, and keep newer files from being dropped.
+        let picker = FifoCompactionPicker::new(FifoCompactionOptions {
+            max_table_files_size: 1000,
+            ttl: 100,
+        });
+        let files = fifo_files(&[(1, 300), (1, 150), (1, 0)]);
+        assert_eq!(picker.pick(&files, 1000), None);
+        let files = fifo_files(&[(1, 300), (1, 0), (1, 150)]);
+        assert_eq!(picker.pick(&files, 1000).unwrap().files, vec![2]);
+    }
+}
@@ -1,3 +1,8 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::result;
+
 /// StatusCode describes various failure modes of database operations.
 #[derive(Clone, Debug, PartialEq)]
 #[allow(dead_code)]
@@ -5,6 +10,7 @@ pub enum StatusCode {
     OK,
 
     AlreadyExists,
+    Busy,
     Corruption,
     CompressionError,
     IOError,
@@ -14,10 +20,9 @@ pub enum StatusCode {
     NotFound,
     NotSupported,
     PermissionDenied,
+    TimedOut,
     AsyncError,
     Unknown,
-    #[cfg(feature = "fs")]
-    Errno(errno::Errno),
 }
 
 /// Status encapsulates a `StatusCode` and an error message. It can be displayed, and also
@@ -38,7 +43,7 @@ impl Default for Status {
 }
 
 impl Display for Status {
-    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+    fn fmt(&self, fmt: &mut Formatter) -> result::Result<(), fmt::Error> {
         fmt.write_str(&self.err)
     }
 }
@@ -50,7 +55,7 @@ impl Error for Status {
 }
 
 impl Status {
-    pub fn new(code: StatusCode, err: String) -> Self {
+    pub fn new(code: StatusCode, err: &str) -> Self {
         let err = if err.is_empty() {
             format!("{:?}", code)
         } else {
@@ -63,11 +68,11 @@ impl Status {
 pub type Result<T> = result::Result<T, Status>;
 
 pub fn err<T>(code: StatusCode, err: String) -> Result<T> {
-    Err(Status::new(code, err))
+    Err(Status::new(code, &err))
 }
 
 impl From<io::Error> for Status {
-    fn from(err: io::Error) -> Self {
+    fn from(e: io::Error) -> Self {
         let c = match e.kind() {
             io::ErrorKind::NotFound => StatusCode::NotFound,
             io::ErrorKind::InvalidData => StatusCode::Corruption,
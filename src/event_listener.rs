@@ -0,0 +1,75 @@
+use crate::errors::Status;
+use crate::ktypes::SeqNum;
+use crate::write_controller::WriteStallInfo;
+
+/// Describes a memtable flush into a new L0 table.
+#[derive(Clone, Debug)]
+pub struct FlushJobInfo {
+    pub file_number: u64,
+    pub file_path: String,
+    pub smallest_seqno: SeqNum,
+    pub largest_seqno: SeqNum,
+    pub num_entries: usize,
+}
+
+/// Describes a compaction; `output_files` is only filled in once the compaction completed, and
+/// `status` tells whether it succeeded.
+#[derive(Clone, Debug)]
+pub struct CompactionJobInfo {
+    pub input_level: usize,
+    pub output_level: usize,
+    pub input_files: Vec<String>,
+    pub output_files: Vec<String>,
+    pub status: Status,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableFileCreationReason {
+    Flush,
+    Compaction,
+}
+
+#[derive(Clone, Debug)]
+pub struct TableFileCreationInfo {
+    pub file_path: String,
+    pub file_size: u64,
+    pub level: usize,
+    pub reason: TableFileCreationReason,
+    pub status: Status,
+}
+
+#[derive(Clone, Debug)]
+pub struct TableFileDeletionInfo {
+    pub file_path: String,
+    pub status: Status,
+}
+
+/// The background operation that failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackgroundErrorReason {
+    Flush,
+    Compaction,
+    ManifestWrite,
+}
+
+/// EventListener receives callbacks about background work and state changes of a database.
+/// Listeners are registered in `Options::listeners`; every method has an empty default
+/// implementation, so that implementors only override what they're interested in.
+///
+/// Callbacks are invoked on the thread doing the work, so they should return quickly.
+pub trait EventListener: Send + Sync {
+    fn on_flush_begin(&self, _info: &FlushJobInfo) {}
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    fn on_compaction_begin(&self, _info: &CompactionJobInfo) {}
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {}
+
+    fn on_table_file_created(&self, _info: &TableFileCreationInfo) {}
+    fn on_table_file_deleted(&self, _info: &TableFileDeletionInfo) {}
+
+    /// Called when writes start or stop being delayed or stopped.
+    fn on_stall_conditions_changed(&self, _info: &WriteStallInfo) {}
+
+    /// Called when a background operation fails.
+    fn on_background_error(&self, _reason: BackgroundErrorReason, _status: &Status) {}
+}
@@ -46,4 +46,13 @@ pub trait LdbIterator {
         self.reset();
         self.advance();
     }
-}
\ No newline at end of file
+}
+/// Returns the key and value the iterator is positioned at, if it is valid.
+pub fn current_key_val<It: LdbIterator + ?Sized>(it: &It) -> Option<(Vec<u8>, Vec<u8>)> {
+    let (mut k, mut v) = (vec![], vec![]);
+    if it.current(&mut k, &mut v) {
+        Some((k, v))
+    } else {
+        None
+    }
+}
@@ -6,6 +6,8 @@ use crate::cmp::Cmp;
 pub enum ValueType {
     TypeDeletion = 0,
     TypeValue = 1,
+    /// The value is a `BlobIndex` pointing into a blob file.
+    TypeBlobIndex = 2,
 }
 
 pub type SeqNum = u64;
@@ -95,6 +97,7 @@ pub fn parse_tag(tag: u64) -> (u64, ValueType) {
     match typ {
         0 => (seq, ValueType::TypeDeletion),
         1 => (seq, ValueType::TypeValue),
+        2 => (seq, ValueType::TypeBlobIndex),
         _ => panic!("invalid tag: {}", tag),
     }
 }
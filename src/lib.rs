@@ -2,4 +2,16 @@ mod cmp;
 mod ktypes;
 mod types;
 mod skiplist;
-mod iterator;
\ No newline at end of file
+mod iterator;
+#[cfg(test)]
+mod test_util;
+mod errors;
+mod lock_manager;
+mod write_batch_with_index;
+mod slice_transform;
+mod statistics;
+mod rate_limiter;
+mod write_controller;
+mod event_listener;
+mod blob;
+mod compaction_picker;
@@ -0,0 +1,351 @@
+use crate::errors::{err, Result, StatusCode};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Identifies the transaction owning or waiting for a lock.
+pub type TxnId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// The holders of a single row lock. A key is either held exclusively by one transaction, or
+/// shared by any number of transactions.
+#[derive(Default)]
+struct LockEntry {
+    exclusive: Option<TxnId>,
+    shared: HashSet<TxnId>,
+}
+
+impl LockEntry {
+    /// Returns the transactions that prevent `txn` from taking the lock in `mode`.
+    fn blockers(&self, txn: TxnId, mode: LockMode) -> HashSet<TxnId> {
+        let mut blockers = HashSet::new();
+        if let Some(owner) = self.exclusive {
+            if owner != txn {
+                blockers.insert(owner);
+            }
+        }
+        if mode == LockMode::Exclusive {
+            blockers.extend(self.shared.iter().copied().filter(|t| *t != txn));
+        }
+        blockers
+    }
+
+    fn is_free(&self) -> bool {
+        self.exclusive.is_none() && self.shared.is_empty()
+    }
+}
+
+/// The lock a transaction is waiting for, and the transactions it is blocked by.
+struct Wait {
+    key: Vec<u8>,
+    mode: LockMode,
+    blockers: HashSet<TxnId>,
+}
+
+#[derive(Default)]
+struct LockTable {
+    locks: HashMap<Vec<u8>, LockEntry>,
+    /// keys locked by each transaction, so that all of them can be released at once.
+    held: HashMap<TxnId, HashSet<Vec<u8>>>,
+    /// the wait-for graph: a waiting transaction points to the transactions it is blocked by.
+    waits_for: HashMap<TxnId, Wait>,
+}
+
+impl LockTable {
+    /// Returns true if `txn` is reachable from any of `from` in the wait-for graph, i.e. if making
+    /// `txn` wait for `from` would close a cycle.
+    fn reaches(&self, from: &HashSet<TxnId>, txn: TxnId) -> bool {
+        let mut stack: Vec<TxnId> = from.iter().copied().collect();
+        let mut visited = HashSet::new();
+
+        while let Some(t) = stack.pop() {
+            if t == txn {
+                return true;
+            }
+            if !visited.insert(t) {
+                continue;
+            }
+            if let Some(wait) = self.waits_for.get(&t) {
+                stack.extend(wait.blockers.iter().copied());
+            }
+        }
+        false
+    }
+
+    fn grant(&mut self, txn: TxnId, key: &[u8], mode: LockMode) {
+        let entry = self.locks.entry(key.to_vec()).or_default();
+        match mode {
+            LockMode::Shared => {
+                if entry.exclusive != Some(txn) {
+                    entry.shared.insert(txn);
+                }
+            }
+            LockMode::Exclusive => {
+                // An upgrade replaces the shared lock held by the same transaction.
+                entry.shared.remove(&txn);
+                entry.exclusive = Some(txn);
+            }
+        }
+        self.held.entry(txn).or_default().insert(key.to_vec());
+    }
+
+    /// Recomputes the blockers of every waiting transaction after locks were released, so that
+    /// deadlock detection doesn't follow edges to transactions that no longer block anyone. The
+    /// waiters themselves only notice once they wake up.
+    fn refresh_waits(&mut self) {
+        let locks = &self.locks;
+        for (txn, wait) in self.waits_for.iter_mut() {
+            wait.blockers = locks
+                .get(&wait.key)
+                .map(|e| e.blockers(*txn, wait.mode))
+                .unwrap_or_default();
+        }
+    }
+
+    fn release(&mut self, txn: TxnId, key: &[u8]) {
+        if let Some(entry) = self.locks.get_mut(key) {
+            if entry.exclusive == Some(txn) {
+                entry.exclusive = None;
+            }
+            entry.shared.remove(&txn);
+            if entry.is_free() {
+                self.locks.remove(key);
+            }
+        }
+    }
+}
+
+/// LockManager hands out per-key shared and exclusive locks to transactions. A transaction that
+/// cannot obtain a lock waits until the lock is released or the lock timeout expires; if waiting
+/// would create a cycle in the wait-for graph, the request fails immediately instead.
+pub struct LockManager {
+    table: Mutex<LockTable>,
+    released: Condvar,
+    lock_timeout: Duration,
+    deadlock_detect: bool,
+}
+
+impl LockManager {
+    pub fn new(lock_timeout: Duration, deadlock_detect: bool) -> LockManager {
+        LockManager {
+            table: Mutex::new(LockTable::default()),
+            released: Condvar::new(),
+            lock_timeout,
+            deadlock_detect,
+        }
+    }
+
+    /// Acquires the lock on `key` in `mode` for `txn`. Asking for a lock the transaction already
+    /// holds succeeds right away, and a shared lock is upgraded if the same transaction asks for an
+    /// exclusive one. Acquisitions are not counted: a single `unlock()` releases the lock.
+    ///
+    /// Fails with `StatusCode::TimedOut` if the lock could not be obtained within the lock
+    /// timeout, and with `StatusCode::Busy` if waiting for it would deadlock.
+    pub fn lock(&self, txn: TxnId, key: &[u8], mode: LockMode) -> Result<()> {
+        let deadline = Instant::now() + self.lock_timeout;
+        let mut table = self.table.lock().unwrap();
+
+        loop {
+            let blockers = table
+                .locks
+                .get(key)
+                .map(|e| e.blockers(txn, mode))
+                .unwrap_or_default();
+
+            if blockers.is_empty() {
+                table.waits_for.remove(&txn);
+                table.grant(txn, key, mode);
+                return Ok(());
+            }
+
+            if self.deadlock_detect && table.reaches(&blockers, txn) {
+                table.waits_for.remove(&txn);
+                return err(
+                    StatusCode::Busy,
+                    format!("deadlock detected for transaction {}", txn),
+                );
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                table.waits_for.remove(&txn);
+                return err(
+                    StatusCode::TimedOut,
+                    format!("transaction {} timed out waiting for lock", txn),
+                );
+            }
+
+            table.waits_for.insert(
+                txn,
+                Wait {
+                    key: key.to_vec(),
+                    mode,
+                    blockers,
+                },
+            );
+            table = self.wait(table, deadline - now);
+        }
+    }
+
+    fn wait<'a>(
+        &self,
+        table: MutexGuard<'a, LockTable>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, LockTable> {
+        self.released.wait_timeout(table, timeout).unwrap().0
+    }
+
+    /// Releases the lock `txn` holds on `key`, if any, regardless of how often it was acquired.
+    pub fn unlock(&self, txn: TxnId, key: &[u8]) {
+        let mut table = self.table.lock().unwrap();
+        table.release(txn, key);
+        if let Some(keys) = table.held.get_mut(&txn) {
+            keys.remove(key);
+            if keys.is_empty() {
+                table.held.remove(&txn);
+            }
+        }
+        table.refresh_waits();
+        self.released.notify_all();
+    }
+
+    /// Releases every lock held by `txn`. This is called when a transaction commits or rolls back.
+    pub fn unlock_all(&self, txn: TxnId) {
+        let mut table = self.table.lock().unwrap();
+        if let Some(keys) = table.held.remove(&txn) {
+            for key in keys {
+                table.release(txn, &key);
+            }
+        }
+        table.waits_for.remove(&txn);
+        table.refresh_waits();
+        self.released.notify_all();
+    }
+
+    /// Returns the number of keys currently locked by any transaction.
+    pub fn num_locked_keys(&self) -> usize {
+        self.table.lock().unwrap().locks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    fn short_timeout() -> LockManager {
+        LockManager::new(Duration::from_millis(20), true)
+    }
+
+    #[test]
+    fn test_lock_manager_shared_locks_are_compatible() {
+        let lm = short_timeout();
+        assert!(lm.lock(1, b"abc", LockMode::Shared).is_ok());
+        assert!(lm.lock(2, b"abc", LockMode::Shared).is_ok());
+        assert_eq!(
+            lm.lock(3, b"abc", LockMode::Exclusive).unwrap_err().code,
+            StatusCode::TimedOut
+        );
+        assert_eq!(lm.num_locked_keys(), 1);
+    }
+
+    #[test]
+    fn test_lock_manager_exclusive_lock() {
+        let lm = short_timeout();
+        assert!(lm.lock(1, b"abc", LockMode::Exclusive).is_ok());
+        // reentrant
+        assert!(lm.lock(1, b"abc", LockMode::Exclusive).is_ok());
+        assert!(lm.lock(1, b"abc", LockMode::Shared).is_ok());
+        assert_eq!(
+            lm.lock(2, b"abc", LockMode::Shared).unwrap_err().code,
+            StatusCode::TimedOut
+        );
+        assert!(lm.lock(2, b"abd", LockMode::Exclusive).is_ok());
+
+        // a single unlock releases the lock taken three times.
+        lm.unlock(1, b"abc");
+        assert!(lm.lock(2, b"abc", LockMode::Shared).is_ok());
+    }
+
+    #[test]
+    fn test_lock_manager_upgrade() {
+        let lm = short_timeout();
+        assert!(lm.lock(1, b"abc", LockMode::Shared).is_ok());
+        assert!(lm.lock(1, b"abc", LockMode::Exclusive).is_ok());
+        assert!(lm.lock(2, b"abc", LockMode::Shared).is_err());
+
+        lm.unlock_all(1);
+        assert_eq!(lm.num_locked_keys(), 0);
+        assert!(lm.lock(2, b"abc", LockMode::Shared).is_ok());
+        // the other holder of a shared lock prevents an upgrade.
+        assert!(lm.lock(3, b"abc", LockMode::Shared).is_ok());
+        assert!(lm.lock(2, b"abc", LockMode::Exclusive).is_err());
+    }
+
+    #[test]
+    fn test_lock_manager_waiter_is_woken_up() {
+        let lm = Arc::new(LockManager::new(Duration::from_secs(10), true));
+        lm.lock(1, b"abc", LockMode::Exclusive).unwrap();
+
+        let lm2 = lm.clone();
+        let waiter = thread::spawn(move || lm2.lock(2, b"abc", LockMode::Exclusive));
+
+        thread::sleep(Duration::from_millis(20));
+        lm.unlock_all(1);
+        assert!(waiter.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_lock_manager_deadlock() {
+        let lm = Arc::new(LockManager::new(Duration::from_secs(10), true));
+        lm.lock(1, b"a", LockMode::Exclusive).unwrap();
+        lm.lock(2, b"b", LockMode::Exclusive).unwrap();
+
+        let lm2 = lm.clone();
+        let waiter = thread::spawn(move || lm2.lock(1, b"b", LockMode::Exclusive));
+
+        // wait until transaction 1 is registered as waiting for transaction 2.
+        while !lm.table.lock().unwrap().waits_for.contains_key(&1) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            lm.lock(2, b"a", LockMode::Exclusive).unwrap_err().code,
+            StatusCode::Busy
+        );
+
+        // rolling back transaction 2 lets transaction 1 proceed.
+        lm.unlock_all(2);
+        assert!(waiter.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_lock_manager_no_deadlock_after_unlock() {
+        let lm = Arc::new(LockManager::new(Duration::from_secs(10), true));
+        lm.lock(1, b"a", LockMode::Exclusive).unwrap();
+        lm.lock(2, b"b", LockMode::Exclusive).unwrap();
+
+        let lm2 = lm.clone();
+        let waiter = thread::spawn(move || {
+            let r = lm2.lock(1, b"b", LockMode::Exclusive);
+            lm2.unlock_all(1);
+            r
+        });
+        while !lm.table.lock().unwrap().waits_for.contains_key(&1) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // once "b" is released, transaction 1 no longer waits for transaction 2, so waiting for
+        // "a" doesn't close a cycle.
+        lm.unlock(2, b"b");
+        assert!(lm.lock(2, b"a", LockMode::Exclusive).is_ok());
+        assert!(waiter.join().unwrap().is_ok());
+    }
+}
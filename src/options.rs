@@ -21,4 +21,31 @@ pub struct Options {
     pub reuse_logs: bool,
     pub reuse_manifest: bool,
     pub filter_policy: filter::BoxedFilterPolicy,
+    /// Extracts key prefixes. Reserved for prefix filters, which are not implemented yet; nothing
+    /// reads this so far.
+    pub prefix_extractor: Option<Rc<Box<dyn SliceTransform>>>,
+    /// If set, tickers and histograms about the database's operations are collected here.
+    pub statistics: Option<Arc<Statistics>>,
+    /// If set, limits the bytes written per second by flushes and compactions.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Writes are delayed once L0 has this many files, and stopped at the stop trigger.
+    pub level0_slowdown_writes_trigger: usize,
+    pub level0_stop_writes_trigger: usize,
+    /// Writes are delayed once the estimated bytes pending compaction exceed the soft limit, and
+    /// stopped past the hard limit. 0 disables a limit.
+    pub soft_pending_compaction_bytes_limit: u64,
+    pub hard_pending_compaction_bytes_limit: u64,
+    /// Bytes per second that may be written while writes are delayed.
+    pub delayed_write_rate: u64,
+    /// Receive callbacks about flushes, compactions, table files, write stalls and background
+    /// errors.
+    pub listeners: Vec<Arc<dyn EventListener>>,
+    /// If set, values of at least this size are written to blob files, and only a `BlobIndex`
+    /// pointing to them is kept in the tables.
+    pub min_blob_size: Option<usize>,
+    pub compaction_style: CompactionStyle,
+    /// Number of L0 files (or, with universal compaction, sorted runs) that triggers a compaction.
+    pub level0_file_num_compaction_trigger: usize,
+    pub compaction_options_universal: UniversalCompactionOptions,
+    pub compaction_options_fifo: FifoCompactionOptions,
 }
\ No newline at end of file
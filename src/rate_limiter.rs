@@ -0,0 +1,228 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The priority of an IO request. Flushes are `High`, so that they are not held up behind
+/// compactions; a full memtable blocks writes, while compaction can catch up later.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoPriority {
+    Low = 0,
+    High = 1,
+}
+
+/// With auto-tuning, the rate never drops below this fraction of the configured rate.
+const MIN_TUNED_RATE_DIVISOR: u64 = 20;
+
+struct State {
+    rate_bytes_per_sec: u64,
+    available: u64,
+    last_refill: Instant,
+    high_pri_waiting: usize,
+    total_bytes: [u64; 2],
+}
+
+/// RateLimiter throttles the bytes written by flushes and compactions using a token bucket. The
+/// bucket holds at most the bytes of one refill period, so that bursts stay short; requests larger
+/// than that are granted in pieces.
+///
+/// Low priority requests are only served when no high priority request is waiting.
+pub struct RateLimiter {
+    state: Mutex<State>,
+    refilled: Condvar,
+    refill_period: Duration,
+    max_bytes_per_sec: u64,
+    auto_tune: bool,
+}
+
+impl RateLimiter {
+    /// Creates a RateLimiter that allows `rate_bytes_per_sec`. If `auto_tune` is set, this is the
+    /// maximum rate, and the actual rate follows the compaction debt passed to `tune()`.
+    pub fn new(rate_bytes_per_sec: u64, refill_period: Duration, auto_tune: bool) -> RateLimiter {
+        assert!(rate_bytes_per_sec > 0);
+        assert!(refill_period > Duration::ZERO);
+        let rate = if auto_tune {
+            rate_bytes_per_sec / MIN_TUNED_RATE_DIVISOR
+        } else {
+            rate_bytes_per_sec
+        }
+        .max(1);
+
+        RateLimiter {
+            state: Mutex::new(State {
+                rate_bytes_per_sec: rate,
+                available: burst(rate, refill_period),
+                last_refill: Instant::now(),
+                high_pri_waiting: 0,
+                total_bytes: [0, 0],
+            }),
+            refilled: Condvar::new(),
+            refill_period,
+            max_bytes_per_sec: rate_bytes_per_sec,
+            auto_tune,
+        }
+    }
+
+    /// Blocks until `bytes` may be written at priority `pri`.
+    pub fn request(&self, bytes: usize, pri: IoPriority) {
+        let mut remaining = bytes as u64;
+        let mut state = self.state.lock().unwrap();
+        state.total_bytes[pri as usize] += remaining;
+
+        while remaining > 0 {
+            self.refill(&mut state);
+            let chunk = remaining.min(burst(state.rate_bytes_per_sec, self.refill_period));
+            let may_take = pri == IoPriority::High || state.high_pri_waiting == 0;
+
+            if may_take && state.available >= chunk {
+                state.available -= chunk;
+                remaining -= chunk;
+                continue;
+            }
+
+            // Sleep until enough tokens for the chunk have accumulated.
+            let missing = chunk.saturating_sub(state.available).max(1);
+            let wait = Duration::from_micros(
+                (missing as u128 * 1_000_000 / state.rate_bytes_per_sec as u128) as u64,
+            )
+            .max(Duration::from_millis(1));
+
+            if pri == IoPriority::High {
+                state.high_pri_waiting += 1;
+            }
+            state = self.refilled.wait_timeout(state, wait).unwrap().0;
+            if pri == IoPriority::High {
+                state.high_pri_waiting -= 1;
+            }
+        }
+        // A waiting low priority request may now proceed.
+        self.refilled.notify_all();
+    }
+
+    fn refill(&self, state: &mut State) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill);
+        let new_tokens =
+            (elapsed.as_micros() * state.rate_bytes_per_sec as u128 / 1_000_000) as u64;
+        // Only advance the clock if tokens were added, so that fractions are not lost.
+        if new_tokens > 0 {
+            state.available = (state.available + new_tokens)
+                .min(burst(state.rate_bytes_per_sec, self.refill_period));
+            state.last_refill = now;
+        }
+    }
+
+    pub fn get_bytes_per_second(&self) -> u64 {
+        self.state.lock().unwrap().rate_bytes_per_sec
+    }
+
+    pub fn set_bytes_per_second(&self, rate_bytes_per_sec: u64) {
+        assert!(rate_bytes_per_sec > 0);
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+        state.rate_bytes_per_sec = rate_bytes_per_sec;
+        state.available = state
+            .available
+            .min(burst(rate_bytes_per_sec, self.refill_period));
+    }
+
+    /// Adjusts the rate to the pending compaction debt if auto-tuning is enabled: the rate grows
+    /// linearly from 1/20th of the maximum rate with no debt, to the maximum rate once the debt
+    /// reaches `debt_limit`. Little debt means compactions can run slowly in the background,
+    /// while a large debt must be paid off before it stalls writes.
+    pub fn tune(&self, pending_compaction_bytes: u64, debt_limit: u64) {
+        if !self.auto_tune {
+            return;
+        }
+        let min_rate = (self.max_bytes_per_sec / MIN_TUNED_RATE_DIVISOR).max(1);
+        let rate = if debt_limit == 0 || pending_compaction_bytes >= debt_limit {
+            self.max_bytes_per_sec
+        } else {
+            let extra = (self.max_bytes_per_sec - min_rate) as u128
+                * pending_compaction_bytes as u128
+                / debt_limit as u128;
+            min_rate + extra as u64
+        };
+        self.set_bytes_per_second(rate);
+    }
+
+    /// Returns the total number of bytes requested at priority `pri`.
+    pub fn get_total_bytes_through(&self, pri: IoPriority) -> u64 {
+        self.state.lock().unwrap().total_bytes[pri as usize]
+    }
+}
+
+/// The bucket size: the bytes allowed within one refill period.
+fn burst(rate_bytes_per_sec: u64, refill_period: Duration) -> u64 {
+    ((rate_bytes_per_sec as u128 * refill_period.as_micros() / 1_000_000) as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_rate_limiter_throttles() {
+        // 10 KiB per 10ms.
+        let rl = RateLimiter::new(1 << 20, Duration::from_millis(10), false);
+        let burst = burst(1 << 20, Duration::from_millis(10)) as usize;
+
+        let start = Instant::now();
+        // The first burst is available right away.
+        rl.request(burst, IoPriority::Low);
+        rl.request(3 * burst, IoPriority::High);
+        assert!(start.elapsed() >= Duration::from_millis(25));
+
+        assert_eq!(rl.get_total_bytes_through(IoPriority::Low), burst as u64);
+        assert_eq!(
+            rl.get_total_bytes_through(IoPriority::High),
+            3 * burst as u64
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter_concurrent_requests() {
+        let rl = Arc::new(RateLimiter::new(1 << 20, Duration::from_millis(10), false));
+        let threads: Vec<_> = [IoPriority::Low, IoPriority::High, IoPriority::Low]
+            .into_iter()
+            .map(|pri| {
+                let rl = rl.clone();
+                thread::spawn(move || {
+                    for _ in 0..4 {
+                        rl.request(4096, pri);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(rl.get_total_bytes_through(IoPriority::Low), 2 * 4 * 4096);
+        assert_eq!(rl.get_total_bytes_through(IoPriority::High), 4 * 4096);
+    }
+
+    #[test]
+    fn test_rate_limiter_tune() {
+        let rl = RateLimiter::new(2000, Duration::from_millis(100), true);
+        assert_eq!(rl.get_bytes_per_second(), 100);
+
+        rl.tune(500, 1000);
+        assert_eq!(rl.get_bytes_per_second(), 1050);
+        rl.tune(5000, 1000);
+        assert_eq!(rl.get_bytes_per_second(), 2000);
+        rl.tune(0, 1000);
+        assert_eq!(rl.get_bytes_per_second(), 100);
+
+        // without auto-tuning, the rate stays put.
+        let rl = RateLimiter::new(2000, Duration::from_millis(100), false);
+        rl.tune(0, 1000);
+        assert_eq!(rl.get_bytes_per_second(), 2000);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rate_limiter_zero_refill_period() {
+        RateLimiter::new(1 << 20, Duration::ZERO, false);
+    }
+}
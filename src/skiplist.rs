@@ -96,9 +96,21 @@ impl SkipMap {
     }
 
     pub fn iter(&self) -> SkipMapIter {
+        self.iter_with_bounds(None, None)
+    }
+
+    /// Returns an iterator that only visits keys in `[lower, upper)`, as ordered by the map's
+    /// comparator. A missing bound leaves that end of the map open.
+    ///
+    /// Bounds are compared like keys of the map, so for a memtable map they have to be encoded mem
+    /// keys: to bound by user keys, build them with `build_mem_key()` and `MAX_SEQUENCE_NUMBER`,
+    /// which sorts in front of all entries of the same user key.
+    pub fn iter_with_bounds(&self, lower: Option<Vec<u8>>, upper: Option<Vec<u8>>) -> SkipMapIter {
         SkipMapIter {
             map: self.map.clone(),
             current: self.map.borrow().head.as_ref() as *const Node,
+            lower_bound: lower,
+            upper_bound: upper,
         }
     }
 }
@@ -131,7 +143,7 @@ impl InnerSkipMap {
 
         loop {
             unsafe {
-                if let Some(next) = (*current).skips[level] {
+                if let Some(next) = (&(*current).skips)[level] {
                     let ord = self.cmp.cmp((*next).key.as_slice(), key);
 
                     match ord {
@@ -174,7 +186,7 @@ impl InnerSkipMap {
 
         loop {
             unsafe {
-                if let Some(next) = (*current).skips[level] {
+                if let Some(next) = (&(*current).skips)[level] {
                     let ord = self.cmp.cmp((*next).key.as_slice(), key);
 
                     if let Ordering::Less = ord {
@@ -220,7 +232,7 @@ impl InnerSkipMap {
         // immediately smaller than the key to be inserted.
         loop {
             unsafe {
-                if let Some(next) = (*current).skips[level] {
+                if let Some(next) = (&(*current).skips)[level] {
                     // If the wanted position is after the current node
                     let ord = self.cmp.cmp(&(*next).key, &key);
 
@@ -258,8 +270,8 @@ impl InnerSkipMap {
         (0..new_height).for_each(|i| {
             if let Some(prev) = prevs[i] {
                 unsafe {
-                    new.skips[i] = (*prev).skips[i];
-                    (*prev).skips[i] = Some(newp);
+                    new.skips[i] = (&(*prev).skips)[i];
+                    (&mut (*prev).skips)[i] = Some(newp);
                 }
             }
         });
@@ -291,7 +303,7 @@ impl InnerSkipMap {
                     (*current).value,
                     (*current).skips
                 );
-                if let Some(next) = (*current).skips[0] {
+                if let Some(next) = (&(*current).skips)[0] {
                     current = next;
                 } else {
                     break;
@@ -304,10 +316,43 @@ impl InnerSkipMap {
 pub struct SkipMapIter {
     map: Rc<RefCell<InnerSkipMap>>,
     current: *const Node,
+    // inclusive
+    lower_bound: Option<Vec<u8>>,
+    // exclusive
+    upper_bound: Option<Vec<u8>>,
+}
+
+impl SkipMapIter {
+    /// Resets the iterator if the current node lies outside of the bounds. Returns whether the
+    /// iterator is still valid.
+    fn check_bounds(&mut self) -> bool {
+        if !self.valid() {
+            return false;
+        }
+        let out_of_bounds = {
+            let map = self.map.borrow();
+            let key = unsafe { &(*self.current).key };
+            let below = |lower: &Vec<u8>| map.cmp.cmp(key, lower) == Ordering::Less;
+            let above = |upper: &Vec<u8>| map.cmp.cmp(key, upper) != Ordering::Less;
+            self.lower_bound.as_ref().is_some_and(below)
+                || self.upper_bound.as_ref().is_some_and(above)
+        };
+        if out_of_bounds {
+            self.reset();
+        }
+        !out_of_bounds
+    }
 }
 
 impl LdbIterator for SkipMapIter {
     fn advance(&mut self) -> bool {
+        // Starting from before the first element, jump straight to the lower bound.
+        if !self.valid() {
+            if let Some(lower) = self.lower_bound.clone() {
+                self.seek(&lower);
+                return self.valid();
+            }
+        }
         // we first go to the next element, then return that -- in order to skip the head node
         let r = unsafe {
             (*self.current)
@@ -322,17 +367,26 @@ impl LdbIterator for SkipMapIter {
         if !r {
             self.reset();
         }
-        r
+        r && self.check_bounds()
     }
     fn reset(&mut self) {
         self.current = self.map.borrow().head.as_ref();
     }
     fn seek(&mut self, key: &[u8]) {
-        if let Some(node) = self.map.borrow().get_greater_or_equal(key) {
-            self.current = node as *const Node;
-            return;
+        let node = {
+            let map = self.map.borrow();
+            // Never position the iterator before the lower bound.
+            let key = match self.lower_bound.as_ref() {
+                Some(lower) if map.cmp.cmp(key, lower) == Ordering::Less => lower.as_slice(),
+                _ => key,
+            };
+            map.get_greater_or_equal(key).map(|n| n as *const Node)
+        };
+        match node {
+            Some(node) => self.current = node,
+            None => self.reset(),
         }
-        self.reset();
+        self.check_bounds();
     }
     fn valid(&self) -> bool {
         self.current != self.map.borrow().head.as_ref()
@@ -353,14 +407,15 @@ impl LdbIterator for SkipMapIter {
     fn prev(&mut self) -> bool {
         // Going after the original implementation here; we just seek to the node before current().
         if self.valid() {
-            if let Some(prev) = self
+            let prev = self
                 .map
                 .borrow()
                 .get_next_smaller(unsafe { &(*self.current).key })
-            {
-                self.current = prev as *const Node;
-                if !prev.key.is_empty() {
-                    return true;
+                .map(|n| n as *const Node);
+            if let Some(prev) = prev {
+                self.current = prev;
+                if unsafe { !(*prev).key.is_empty() } {
+                    return self.check_bounds();
                 }
             }
         }
@@ -372,10 +427,18 @@ impl LdbIterator for SkipMapIter {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::cmp::MemKeyCmp;
+    use crate::cmp::{DefaultCmp, MemKeyCmp};
+    use crate::iterator::current_key_val;
+    use crate::ktypes::{build_mem_key, parse_mem_key, ValueType};
+    use crate::test_util::{test_iterator_properties, LdbIteratorIter};
+    use crate::types::MAX_SEQUENCE_NUMBER;
+
+    fn default_cmp() -> Rc<Box<dyn Cmp>> {
+        Rc::new(Box::new(DefaultCmp))
+    }
 
     pub fn make_skipmap() -> SkipMap {
-        let mut skm = SkipMap::new(options::for_test().cmp);
+        let mut skm = SkipMap::new(default_cmp());
         let keys = vec![
             "aba", "abb", "abc", "abd", "abe", "abf", "abg", "abh", "abi", "abj", "abk", "abl",
             "abm", "abn", "abo", "abp", "abq", "abr", "abs", "abt", "abu", "abv", "abw", "abx",
@@ -470,7 +533,7 @@ pub mod tests {
     #[test]
     fn test_empty_skipmap_find_memtable_cmp() {
         // Regression test: Make sure comparator isn't called with empty key.
-        let cmp: Rc<Box<dyn Cmp>> = Rc::new(Box::new(MemtableKeyCmp(options::for_test().cmp)));
+        let cmp: Rc<Box<dyn Cmp>> = Rc::new(Box::new(MemKeyCmp(default_cmp())));
         let skm = SkipMap::new(cmp);
 
         let mut it = skm.iter();
@@ -480,7 +543,7 @@ pub mod tests {
 
     #[test]
     fn test_skipmap_iterator_0() {
-        let skm = SkipMap::new(options::for_test().cmp);
+        let skm = SkipMap::new(default_cmp());
         let mut i = 0;
 
         for (_, _) in LdbIteratorIter::wrap(&mut skm.iter()) {
@@ -512,7 +575,7 @@ pub mod tests {
         let skm = make_skipmap();
         let mut i = 0;
 
-        for (k, v) in LdbIteratorIter::wrp(&mut skm.iter()) {
+        for (k, v) in LdbIteratorIter::wrap(&mut skm.iter()) {
             assert!(!k.is_empty());
             assert!(!v.is_empty());
             i += 1;
@@ -553,7 +616,7 @@ pub mod tests {
 
     #[test]
     fn test_skipmap_behavior() {
-        let mut skm = SkipMap::new(options::for_test().cmp);
+        let mut skm = SkipMap::new(default_cmp());
         let keys = vec!["aba", "abb", "abc", "abd"];
         for k in keys {
             skm.insert(k.as_bytes().to_vec(), "def".as_bytes().to_vec());
@@ -580,7 +643,6 @@ pub mod tests {
 
     #[test]
     fn test_skipmap_iterator_concurrent_insert() {
-        time_test!();
         // Asserts that the map can be mutated while an iterator exists; this is intentional.
         let mut skm = make_skipmap();
         let mut iter = skm.iter();
@@ -595,4 +657,64 @@ pub mod tests {
         }
         panic!("abccc not found in map.");
     }
+
+    #[test]
+    fn test_skipmap_iterator_bounds() {
+        let skm = make_skipmap();
+        let mut iter = skm.iter_with_bounds(Some(b"abc".to_vec()), Some(b"abf".to_vec()));
+
+        let mut keys = vec![];
+        while let Some((k, _)) = iter.next() {
+            keys.push(k);
+        }
+        assert_eq!(
+            keys,
+            vec![b"abc".to_vec(), b"abd".to_vec(), b"abe".to_vec()]
+        );
+        assert!(!iter.valid());
+
+        // seeking below the lower bound lands on the lower bound.
+        iter.seek(b"aaa");
+        assert_eq!(current_key_val(&iter).unwrap().0, b"abc".to_vec());
+        assert!(!iter.prev());
+
+        iter.seek(b"abe");
+        assert!(iter.valid());
+        assert!(!iter.advance());
+        iter.seek(b"abf");
+        assert!(!iter.valid());
+
+        let mut iter = skm.iter_with_bounds(None, Some(b"abb".to_vec()));
+        assert!(iter.advance());
+        assert_eq!(current_key_val(&iter).unwrap().0, b"aba".to_vec());
+        assert!(!iter.advance());
+    }
+
+    #[test]
+    fn test_skipmap_iterator_bounds_memtable_map() {
+        let mut skm = SkipMap::new_memtable_map(default_cmp());
+        for (i, k) in ["aba", "abb", "abb", "abc", "abd"].iter().enumerate() {
+            skm.insert(
+                build_mem_key(k.as_bytes(), b"def", &(i as u64 + 1), &ValueType::TypeValue),
+                vec![],
+            );
+        }
+        let bound = |k: &[u8]| build_mem_key(k, &[], &MAX_SEQUENCE_NUMBER, &ValueType::TypeValue);
+        let mut iter = skm.iter_with_bounds(Some(bound(b"abb")), Some(bound(b"abd")));
+
+        let mut keys = vec![];
+        while let Some((k, _)) = iter.next() {
+            let (ukey, seq, _, _) = parse_mem_key(&k);
+            keys.push((ukey.to_vec(), seq));
+        }
+        // Both entries of the lower bound's key are visited, newest first.
+        assert_eq!(
+            keys,
+            vec![
+                (b"abb".to_vec(), 3),
+                (b"abb".to_vec(), 2),
+                (b"abc".to_vec(), 4)
+            ]
+        );
+    }
 }
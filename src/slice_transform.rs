@@ -0,0 +1,195 @@
+use crate::cmp::Cmp;
+use crate::iterator::LdbIterator;
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// A SliceTransform extracts the prefix of a user key. Prefixes, instead of whole keys, can then
+/// be fed into filters, and iterators can be confined to the keys sharing a prefix.
+pub trait SliceTransform {
+    /// A unique identifier for the transform; it's persisted with the filters built using it.
+    fn name(&self) -> &'static str;
+
+    /// Returns the prefix of `key`. Must only be called for keys that are `in_domain()`.
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8];
+
+    /// Returns true if a prefix can be extracted from `key`. Keys outside of the domain are not
+    /// added to prefix filters, and never rule out a table.
+    fn in_domain(&self, key: &[u8]) -> bool;
+}
+
+/// Uses the first `n` bytes of a key as its prefix. Keys shorter than that have no prefix.
+pub struct FixedPrefixTransform(pub usize);
+impl SliceTransform for FixedPrefixTransform {
+    fn name(&self) -> &'static str {
+        "fundb.FixedPrefix"
+    }
+
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        debug_assert!(self.in_domain(key));
+        &key[..self.0]
+    }
+
+    fn in_domain(&self, key: &[u8]) -> bool {
+        key.len() >= self.0
+    }
+}
+
+/// Uses up to the first `n` bytes of a key as its prefix; shorter keys are their own prefix.
+pub struct CappedPrefixTransform(pub usize);
+impl SliceTransform for CappedPrefixTransform {
+    fn name(&self) -> &'static str {
+        "fundb.CappedPrefix"
+    }
+
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..key.len().min(self.0)]
+    }
+
+    fn in_domain(&self, _key: &[u8]) -> bool {
+        true
+    }
+}
+
+/// PrefixIterator confines an iterator over user keys to the prefix of the key it was last
+/// seeked to: once the wrapped iterator moves past the last key with that prefix (in either
+/// direction), it becomes invalid instead of scanning on.
+///
+/// Seeking to a key outside of the transform's domain leaves the iterator unbounded.
+pub struct PrefixIterator<I: LdbIterator> {
+    iter: I,
+    transform: Rc<Box<dyn SliceTransform>>,
+    ucmp: Rc<Box<dyn Cmp>>,
+    prefix: Option<Vec<u8>>,
+}
+
+impl<I: LdbIterator> PrefixIterator<I> {
+    pub fn new(
+        iter: I,
+        transform: Rc<Box<dyn SliceTransform>>,
+        ucmp: Rc<Box<dyn Cmp>>,
+    ) -> PrefixIterator<I> {
+        PrefixIterator {
+            iter,
+            transform,
+            ucmp,
+            prefix: None,
+        }
+    }
+
+    /// Resets the wrapped iterator if it left the prefix, and returns whether it's still valid.
+    fn check_prefix(&mut self) -> bool {
+        if !self.iter.valid() {
+            return false;
+        }
+        if let Some(prefix) = self.prefix.as_ref() {
+            let (mut k, mut v) = (vec![], vec![]);
+            self.iter.current(&mut k, &mut v);
+            let same = self.transform.in_domain(&k)
+                && self.ucmp.cmp(self.transform.transform(&k), prefix) == Ordering::Equal;
+            if !same {
+                self.iter.reset();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<I: LdbIterator> LdbIterator for PrefixIterator<I> {
+    fn advance(&mut self) -> bool {
+        self.iter.advance() && self.check_prefix()
+    }
+    fn current(&self, key: &mut Vec<u8>, val: &mut Vec<u8>) -> bool {
+        self.iter.current(key, val)
+    }
+    fn seek(&mut self, key: &[u8]) {
+        self.prefix = if self.transform.in_domain(key) {
+            Some(self.transform.transform(key).to_vec())
+        } else {
+            None
+        };
+        self.iter.seek(key);
+        self.check_prefix();
+    }
+    fn reset(&mut self) {
+        self.iter.reset();
+        self.prefix = None;
+    }
+    fn valid(&self) -> bool {
+        self.iter.valid()
+    }
+    fn prev(&mut self) -> bool {
+        self.iter.prev() && self.check_prefix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmp::DefaultCmp;
+    use crate::skiplist::SkipMap;
+
+    #[test]
+    fn test_fixed_prefix_transform() {
+        let t = FixedPrefixTransform(3);
+        assert!(t.in_domain(b"abc"));
+        assert!(t.in_domain(b"abcdef"));
+        assert!(!t.in_domain(b"ab"));
+        assert_eq!(t.transform(b"abcdef"), b"abc");
+        assert_eq!(t.transform(b"abc"), b"abc");
+    }
+
+    #[test]
+    fn test_capped_prefix_transform() {
+        let t = CappedPrefixTransform(3);
+        assert!(t.in_domain(b""));
+        assert_eq!(t.transform(b"abcdef"), b"abc");
+        assert_eq!(t.transform(b"ab"), b"ab");
+        assert_eq!(t.transform(b""), b"");
+    }
+
+    #[test]
+    fn test_prefix_iterator() {
+        let mut skm = SkipMap::new(Rc::new(Box::new(DefaultCmp)));
+        for k in ["aaz", "aba", "abb", "abc", "aca", "b"] {
+            skm.insert(k.as_bytes().to_vec(), b"def".to_vec());
+        }
+        let mut iter = PrefixIterator::new(
+            skm.iter(),
+            Rc::new(Box::new(FixedPrefixTransform(2))),
+            Rc::new(Box::new(DefaultCmp)),
+        );
+        let (mut k, mut v) = (vec![], vec![]);
+
+        iter.seek(b"abb");
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k, b"abb");
+        assert!(iter.advance());
+        assert!(!iter.advance());
+        assert!(!iter.valid());
+
+        iter.seek(b"ab");
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k, b"aba");
+        assert!(!iter.prev());
+
+        // seek_to_first() isn't bound by the prefix of an earlier seek.
+        iter.seek_to_first();
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k, b"aaz");
+        assert!(iter.advance());
+
+        // no key with the prefix "ad" exists.
+        iter.seek(b"ad");
+        assert!(!iter.valid());
+
+        // "a" is outside of the domain: the iterator is unbounded.
+        iter.seek(b"a");
+        let mut n = 1;
+        while iter.advance() {
+            n += 1;
+        }
+        assert_eq!(n, 6);
+    }
+}
@@ -0,0 +1,411 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Tickers are monotonically increasing counters of database events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ticker {
+    BytesWritten,
+    BytesRead,
+    KeysWritten,
+    KeysRead,
+    MemtableHit,
+    MemtableMiss,
+    /// A filter ruled out a table, so that no data block had to be read.
+    BloomFilterUseful,
+    BlockCacheHit,
+    BlockCacheMiss,
+    CompactReadBytes,
+    CompactWriteBytes,
+    FlushWriteBytes,
+    StallMicros,
+    /// Number of times writes were slowed down or stopped, by cause.
+    StallL0SlowdownCount,
+    StallL0StopCount,
+    StallPendingCompactionSlowdownCount,
+    StallPendingCompactionStopCount,
+}
+
+impl Ticker {
+    pub const ALL: [Ticker; 17] = [
+        Ticker::BytesWritten,
+        Ticker::BytesRead,
+        Ticker::KeysWritten,
+        Ticker::KeysRead,
+        Ticker::MemtableHit,
+        Ticker::MemtableMiss,
+        Ticker::BloomFilterUseful,
+        Ticker::BlockCacheHit,
+        Ticker::BlockCacheMiss,
+        Ticker::CompactReadBytes,
+        Ticker::CompactWriteBytes,
+        Ticker::FlushWriteBytes,
+        Ticker::StallMicros,
+        Ticker::StallL0SlowdownCount,
+        Ticker::StallL0StopCount,
+        Ticker::StallPendingCompactionSlowdownCount,
+        Ticker::StallPendingCompactionStopCount,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ticker::BytesWritten => "fundb.bytes.written",
+            Ticker::BytesRead => "fundb.bytes.read",
+            Ticker::KeysWritten => "fundb.number.keys.written",
+            Ticker::KeysRead => "fundb.number.keys.read",
+            Ticker::MemtableHit => "fundb.memtable.hit",
+            Ticker::MemtableMiss => "fundb.memtable.miss",
+            Ticker::BloomFilterUseful => "fundb.bloom.filter.useful",
+            Ticker::BlockCacheHit => "fundb.block.cache.hit",
+            Ticker::BlockCacheMiss => "fundb.block.cache.miss",
+            Ticker::CompactReadBytes => "fundb.compact.read.bytes",
+            Ticker::CompactWriteBytes => "fundb.compact.write.bytes",
+            Ticker::FlushWriteBytes => "fundb.flush.write.bytes",
+            Ticker::StallMicros => "fundb.stall.micros",
+            Ticker::StallL0SlowdownCount => "fundb.stall.l0.slowdown.count",
+            Ticker::StallL0StopCount => "fundb.stall.l0.stop.count",
+            Ticker::StallPendingCompactionSlowdownCount => {
+                "fundb.stall.pending.compaction.slowdown.count"
+            }
+            Ticker::StallPendingCompactionStopCount => "fundb.stall.pending.compaction.stop.count",
+        }
+    }
+}
+
+/// Histograms record the distribution of a measured value, mostly operation latencies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Histogram {
+    GetMicros,
+    WriteMicros,
+    SeekMicros,
+    /// Number of table files looked at by a single get.
+    FilesPerGet,
+}
+
+impl Histogram {
+    pub const ALL: [Histogram; 4] = [
+        Histogram::GetMicros,
+        Histogram::WriteMicros,
+        Histogram::SeekMicros,
+        Histogram::FilesPerGet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Histogram::GetMicros => "fundb.db.get.micros",
+            Histogram::WriteMicros => "fundb.db.write.micros",
+            Histogram::SeekMicros => "fundb.db.seek.micros",
+            Histogram::FilesPerGet => "fundb.files.per.get",
+        }
+    }
+}
+
+/// Returns the (inclusive) upper limits of the histogram buckets. Every bucket is about 1.5 times
+/// wider than the previous one, and the last one covers everything up to `u64::MAX`.
+fn bucket_limits() -> &'static [u64] {
+    static LIMITS: OnceLock<Vec<u64>> = OnceLock::new();
+    LIMITS.get_or_init(|| {
+        let mut limits = vec![1, 2];
+        let mut last = 2u64;
+        while let Some(next) = last.checked_add((last / 2).max(1)) {
+            limits.push(next);
+            last = next;
+        }
+        limits.push(u64::MAX);
+        limits
+    })
+}
+
+struct HistogramImpl {
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+    buckets: Vec<AtomicU64>,
+}
+
+impl HistogramImpl {
+    fn new() -> HistogramImpl {
+        HistogramImpl {
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+            buckets: bucket_limits().iter().map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn add(&self, value: u64) {
+        let bucket = bucket_limits().partition_point(|limit| *limit < value);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.min.fetch_min(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+        for b in self.buckets.iter() {
+            b.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Estimates the value below which `p` percent of the values lie, interpolating linearly
+    /// within the bucket the percentile falls into.
+    fn percentile(&self, p: f64) -> f64 {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return 0.0;
+        }
+        let (min, max) = (
+            self.min.load(Ordering::Relaxed) as f64,
+            self.max.load(Ordering::Relaxed) as f64,
+        );
+        let threshold = count as f64 * p / 100.0;
+        let limits = bucket_limits();
+        let mut cumulative = 0;
+
+        for (i, b) in self.buckets.iter().enumerate() {
+            let in_bucket = b.load(Ordering::Relaxed);
+            cumulative += in_bucket;
+            if cumulative as f64 >= threshold {
+                let left = if i == 0 { 0.0 } else { limits[i - 1] as f64 };
+                let right = limits[i] as f64;
+                let before = (cumulative - in_bucket) as f64;
+                let pos = if in_bucket == 0 {
+                    0.0
+                } else {
+                    (threshold - before) / in_bucket as f64
+                };
+                return (left + (right - left) * pos).clamp(min, max);
+            }
+        }
+        max
+    }
+
+    fn data(&self) -> HistogramData {
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum.load(Ordering::Relaxed);
+        HistogramData {
+            count,
+            sum,
+            min: if count == 0 {
+                0
+            } else {
+                self.min.load(Ordering::Relaxed)
+            },
+            max: self.max.load(Ordering::Relaxed),
+            average: if count == 0 {
+                0.0
+            } else {
+                sum as f64 / count as f64
+            },
+            median: self.percentile(50.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+        }
+    }
+}
+
+/// A snapshot of the values recorded by a histogram.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistogramData {
+    pub count: u64,
+    pub sum: u64,
+    pub min: u64,
+    pub max: u64,
+    pub average: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+/// Statistics collects tickers and histograms for a database. It can be shared between threads;
+/// all updates are lock-free.
+///
+/// `Display` renders a text dump of all tickers and histograms.
+pub struct Statistics {
+    tickers: Vec<AtomicU64>,
+    histograms: Vec<HistogramImpl>,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Statistics::new()
+    }
+}
+
+impl Statistics {
+    pub fn new() -> Statistics {
+        Statistics {
+            tickers: Ticker::ALL.iter().map(|_| AtomicU64::new(0)).collect(),
+            histograms: Histogram::ALL
+                .iter()
+                .map(|_| HistogramImpl::new())
+                .collect(),
+        }
+    }
+
+    pub fn record_tick(&self, ticker: Ticker, count: u64) {
+        self.tickers[ticker as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn get_ticker_count(&self, ticker: Ticker) -> u64 {
+        self.tickers[ticker as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn measure(&self, histogram: Histogram, value: u64) {
+        self.histograms[histogram as usize].add(value);
+    }
+
+    /// Returns a timer that records the microseconds until it is dropped into `histogram`.
+    pub fn timer(&self, histogram: Histogram) -> StatsTimer<'_> {
+        StatsTimer {
+            stats: self,
+            histogram,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn histogram_data(&self, histogram: Histogram) -> HistogramData {
+        self.histograms[histogram as usize].data()
+    }
+
+    /// Sets all tickers and histograms back to zero.
+    pub fn reset(&self) {
+        for t in self.tickers.iter() {
+            t.store(0, Ordering::Relaxed);
+        }
+        for h in self.histograms.iter() {
+            h.clear();
+        }
+    }
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for t in Ticker::ALL.iter() {
+            writeln!(f, "{} COUNT : {}", t.name(), self.get_ticker_count(*t))?;
+        }
+        for h in Histogram::ALL.iter() {
+            let d = self.histogram_data(*h);
+            writeln!(
+                f,
+                "{} P50 : {:.1} P95 : {:.1} P99 : {:.1} P100 : {} COUNT : {} SUM : {}",
+                h.name(),
+                d.median,
+                d.p95,
+                d.p99,
+                d.max,
+                d.count,
+                d.sum
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// StatsTimer measures the time from its creation until it is dropped.
+pub struct StatsTimer<'a> {
+    stats: &'a Statistics,
+    histogram: Histogram,
+    start: Instant,
+}
+
+impl Drop for StatsTimer<'_> {
+    fn drop(&mut self) {
+        let micros = self.start.elapsed().as_micros() as u64;
+        self.stats.measure(self.histogram, micros);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_statistics_tickers() {
+        let stats = Statistics::new();
+        stats.record_tick(Ticker::BytesWritten, 100);
+        stats.record_tick(Ticker::BytesWritten, 23);
+        stats.record_tick(Ticker::MemtableHit, 1);
+
+        assert_eq!(stats.get_ticker_count(Ticker::BytesWritten), 123);
+        assert_eq!(stats.get_ticker_count(Ticker::MemtableHit), 1);
+        assert_eq!(stats.get_ticker_count(Ticker::MemtableMiss), 0);
+
+        stats.reset();
+        assert_eq!(stats.get_ticker_count(Ticker::BytesWritten), 0);
+    }
+
+    #[test]
+    fn test_statistics_tickers_concurrent() {
+        let stats = Arc::new(Statistics::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let stats = stats.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        stats.record_tick(Ticker::KeysRead, 1);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(stats.get_ticker_count(Ticker::KeysRead), 4000);
+    }
+
+    #[test]
+    fn test_statistics_histogram() {
+        let stats = Statistics::new();
+        assert_eq!(
+            stats.histogram_data(Histogram::GetMicros),
+            HistogramData::default()
+        );
+
+        for v in 1..=100 {
+            stats.measure(Histogram::GetMicros, v);
+        }
+        let d = stats.histogram_data(Histogram::GetMicros);
+        assert_eq!(d.count, 100);
+        assert_eq!(d.sum, 5050);
+        assert_eq!(d.min, 1);
+        assert_eq!(d.max, 100);
+        assert_eq!(d.average, 50.5);
+        // percentiles are estimates within a bucket.
+        assert!(d.median > 40.0 && d.median < 60.0, "{}", d.median);
+        assert!(d.p99 > 90.0 && d.p99 <= 100.0, "{}", d.p99);
+
+        stats.measure(Histogram::FilesPerGet, 0);
+        let d = stats.histogram_data(Histogram::FilesPerGet);
+        assert_eq!((d.count, d.min, d.max, d.median), (1, 0, 0, 0.0));
+    }
+
+    #[test]
+    fn test_statistics_timer_and_dump() {
+        let stats = Statistics::new();
+        {
+            let _t = stats.timer(Histogram::SeekMicros);
+        }
+        stats.record_tick(Ticker::StallMicros, 7);
+        assert_eq!(stats.histogram_data(Histogram::SeekMicros).count, 1);
+
+        let dump = stats.to_string();
+        assert!(dump.contains("fundb.stall.micros COUNT : 7\n"));
+        assert!(dump.contains("fundb.db.seek.micros P50 : "));
+        assert_eq!(
+            dump.lines().count(),
+            Ticker::ALL.len() + Histogram::ALL.len()
+        );
+    }
+}
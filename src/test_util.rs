@@ -0,0 +1,62 @@
+use crate::iterator::{current_key_val, LdbIterator};
+
+/// LdbIteratorIter adapts an `LdbIterator` to a standard `Iterator`.
+pub struct LdbIteratorIter<'a, It: 'a> {
+    inner: &'a mut It,
+}
+
+impl<'a, It: LdbIterator> LdbIteratorIter<'a, It> {
+    pub fn wrap(it: &'a mut It) -> LdbIteratorIter<'a, It> {
+        LdbIteratorIter { inner: it }
+    }
+}
+
+impl<It: LdbIterator> Iterator for LdbIteratorIter<'_, It> {
+    type Item = (Vec<u8>, Vec<u8>);
+    fn next(&mut self) -> Option<Self::Item> {
+        LdbIterator::next(self.inner)
+    }
+}
+
+/// Verifies the properties of the `LdbIterator` trait on an iterator over exactly four distinct
+/// entries.
+pub fn test_iterator_properties<It: LdbIterator>(mut it: It) {
+    assert!(!it.valid());
+    assert!(it.advance());
+    assert!(it.valid());
+    let first = current_key_val(&it);
+    assert!(it.advance());
+    let second = current_key_val(&it);
+    assert!(it.advance());
+    let third = current_key_val(&it);
+    // fourth (last) element
+    assert!(it.advance());
+    assert!(it.valid());
+    let fourth = current_key_val(&it);
+    // past end is invalid
+    assert!(!it.advance());
+    assert!(!it.valid());
+
+    it.reset();
+    it.seek(&fourth.as_ref().unwrap().0);
+    assert!(it.valid());
+    it.seek(&second.as_ref().unwrap().0);
+    assert!(it.valid());
+    it.prev();
+    assert_eq!(first, current_key_val(&it));
+
+    it.reset();
+    assert!(!it.valid());
+    assert!(it.advance());
+    assert_eq!(first, current_key_val(&it));
+    assert!(it.advance());
+    assert_eq!(second, current_key_val(&it));
+    assert!(it.advance());
+    assert_eq!(third, current_key_val(&it));
+    assert!(it.prev());
+    assert_eq!(second, current_key_val(&it));
+    assert!(it.prev());
+    assert_eq!(first, current_key_val(&it));
+    assert!(!it.prev());
+    assert!(!it.valid());
+}
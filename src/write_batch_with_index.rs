@@ -0,0 +1,560 @@
+use crate::cmp::Cmp;
+use crate::iterator::LdbIterator;
+use crate::ktypes::{build_mem_key, parse_mem_key, SeqNum, ValueType};
+use crate::skiplist::{SkipMap, SkipMapIter};
+use crate::types::MAX_SEQUENCE_NUMBER;
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// The result of looking up a key in a `WriteBatchWithIndex` alone.
+#[derive(Debug, PartialEq)]
+pub enum BatchLookup {
+    /// The batch contains a value for the key.
+    Found(Vec<u8>),
+    /// The batch deletes the key; the key must not be looked up in the database.
+    Deleted,
+    /// The batch does not touch the key; the database has to be consulted.
+    NotFound,
+}
+
+/// WriteBatchWithIndex is a write batch that keeps its pending entries in a `SkipMap`, so that
+/// they can be read back before the batch is written, either alone (`get_from_batch()`) or merged
+/// on top of a database iterator (`iter_with_base()`).
+///
+/// Entries are stored as mem keys with a sequence number local to the batch; a later write to the
+/// same key thus sorts in front of earlier ones, like in a memtable.
+pub struct WriteBatchWithIndex {
+    index: SkipMap,
+    ucmp: Rc<Box<dyn Cmp>>,
+    seq: SeqNum,
+}
+
+// Blob indices are only created when a flush moves large values out of the LSM tree; a batch
+// only ever holds the puts and deletes added to it.
+const ONLY_PUTS_AND_DELETES: &str = "a write batch only stores puts and deletes";
+
+/// Returns the mem key to seek to in order to find the newest entry for `key` with a sequence
+/// number not greater than `seq`.
+fn seek_key(key: &[u8], seq: SeqNum) -> Vec<u8> {
+    build_mem_key(key, &[], &seq, &ValueType::TypeValue)
+}
+
+impl WriteBatchWithIndex {
+    pub fn new(ucmp: Rc<Box<dyn Cmp>>) -> WriteBatchWithIndex {
+        WriteBatchWithIndex {
+            index: SkipMap::new_memtable_map(ucmp.clone()),
+            ucmp,
+            seq: 0,
+        }
+    }
+
+    pub fn put(&mut self, key: &[u8], val: &[u8]) {
+        self.add(key, val, ValueType::TypeValue);
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.add(key, &[], ValueType::TypeDeletion);
+    }
+
+    fn add(&mut self, key: &[u8], val: &[u8], typ: ValueType) {
+        // batch-local sequence numbers start at 1, so that a seek key with sequence number 0
+        // sorts behind all entries for the same user key.
+        self.seq += 1;
+        self.index
+            .insert(build_mem_key(key, val, &self.seq, &typ), Vec::new());
+    }
+
+    /// Returns the number of operations in the batch, including overwritten ones.
+    pub fn count(&self) -> usize {
+        self.index.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn approx_memory(&self) -> usize {
+        self.index.approx_memory()
+    }
+
+    /// Looks up the most recent operation on `key` in the batch.
+    pub fn get_from_batch(&self, key: &[u8]) -> BatchLookup {
+        let mut iter = self.index.iter();
+        iter.seek(&seek_key(key, MAX_SEQUENCE_NUMBER));
+        match decode_entry(&iter) {
+            Some((k, typ, v)) if self.ucmp.cmp(&k, key) == Ordering::Equal => match typ {
+                ValueType::TypeValue => BatchLookup::Found(v),
+                ValueType::TypeDeletion => BatchLookup::Deleted,
+                ValueType::TypeBlobIndex => unreachable!("{}", ONLY_PUTS_AND_DELETES),
+            },
+            _ => BatchLookup::NotFound,
+        }
+    }
+
+    /// Returns an iterator over `base` with the entries of this batch laid over it: values in the
+    /// batch replace the values of `base`, and deletions in the batch hide keys of `base`.
+    ///
+    /// `base` has to yield user keys ordered by the comparator the batch was created with.
+    pub fn iter_with_base<I: LdbIterator>(&self, base: I) -> BaseDeltaIterator<I> {
+        BaseDeltaIterator {
+            base,
+            delta: self.index.iter(),
+            ucmp: self.ucmp.clone(),
+            forward: true,
+            current: None,
+            current_at_base: false,
+            equal_keys: false,
+        }
+    }
+}
+
+/// Decodes the entry at the position of `iter` into (user key, type, value).
+fn decode_entry(iter: &SkipMapIter) -> Option<(Vec<u8>, ValueType, Vec<u8>)> {
+    let (mut mkey, mut unused) = (vec![], vec![]);
+    if !iter.current(&mut mkey, &mut unused) {
+        return None;
+    }
+    let (k, _, typ, v) = parse_mem_key(&mkey);
+    Some((k.to_vec(), typ, v.to_vec()))
+}
+
+/// Returns the last key of `iter` by walking it from the start; `iter` is left unpositioned.
+fn last_key<I: LdbIterator>(iter: &mut I) -> Option<Vec<u8>> {
+    let (mut k, mut v) = (vec![], vec![]);
+    let mut last = None;
+    iter.reset();
+    while iter.advance() {
+        iter.current(&mut k, &mut v);
+        last = Some(k.clone());
+    }
+    last
+}
+
+/// BaseDeltaIterator merges the entries of a `WriteBatchWithIndex` (the delta) into a base
+/// iterator. Only the newest batch entry for each key is visible.
+///
+/// Like a two-way merging iterator, it keeps both child iterators positioned: moving in one
+/// direction only steps the child(ren) that supplied the current key. Children are only sought
+/// when `seek()` is called or the direction changes.
+pub struct BaseDeltaIterator<I: LdbIterator> {
+    base: I,
+    // Always positioned at the newest entry of a key.
+    delta: SkipMapIter,
+    ucmp: Rc<Box<dyn Cmp>>,
+    forward: bool,
+    current: Option<(Vec<u8>, Vec<u8>)>,
+    current_at_base: bool,
+    // Both children are positioned at the current key; the delta shadows the base.
+    equal_keys: bool,
+}
+
+impl<I: LdbIterator> BaseDeltaIterator<I> {
+    fn base_entry(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (mut k, mut v) = (vec![], vec![]);
+        if self.base.current(&mut k, &mut v) {
+            Some((k, v))
+        } else {
+            None
+        }
+    }
+
+    fn step_base(&mut self) {
+        if self.forward {
+            self.base.advance();
+        } else {
+            self.base.prev();
+        }
+    }
+
+    fn delta_at(&self, key: &[u8]) -> bool {
+        decode_entry(&self.delta).is_some_and(|(k, _, _)| self.ucmp.cmp(&k, key) == Ordering::Equal)
+    }
+
+    /// Moves the delta iterator to the newest entry of the next (previous if `!forward`) key.
+    fn step_delta(&mut self) {
+        let Some((key, _, _)) = decode_entry(&self.delta) else {
+            return;
+        };
+        if self.forward {
+            while self.delta.advance() && self.delta_at(&key) {}
+            return;
+        }
+
+        // Going back lands on the oldest entry of the previous key; continue to the entry in
+        // front of its newest one, and step forward again.
+        if !self.delta.prev() {
+            return;
+        }
+        let Some((key, _, _)) = decode_entry(&self.delta) else {
+            return;
+        };
+        while self.delta.prev() && self.delta_at(&key) {}
+        // If prev() ran off the start, advance() returns to the first entry.
+        self.delta.advance();
+    }
+
+    /// Repositions both children around the current key for iterating in direction `forward`.
+    /// Without a way to seek to the last entry, a child that is past its end when turning
+    /// backwards is walked from the start; this only happens once per change of direction.
+    fn change_direction(&mut self, forward: bool) {
+        let Some((key, _)) = self.current.clone() else {
+            return;
+        };
+        self.forward = forward;
+        self.base.seek(&key);
+        self.delta.seek(&seek_key(&key, MAX_SEQUENCE_NUMBER));
+        if forward {
+            self.update_current();
+            return;
+        }
+
+        // Both children are now at or after the current key; move them to or before it.
+        match self.base_entry() {
+            Some((k, _)) if self.ucmp.cmp(&k, &key) == Ordering::Equal => {}
+            Some(_) => {
+                self.base.prev();
+            }
+            None => {
+                if let Some(last) = last_key(&mut self.base) {
+                    self.base.seek(&last);
+                }
+            }
+        }
+        match decode_entry(&self.delta) {
+            Some((k, _, _)) if self.ucmp.cmp(&k, &key) == Ordering::Equal => {}
+            Some(_) => self.step_delta(),
+            None => {
+                if let Some(last) = last_key(&mut self.delta) {
+                    let (k, _, _, _) = parse_mem_key(&last);
+                    self.delta.seek(&seek_key(k, MAX_SEQUENCE_NUMBER));
+                }
+            }
+        }
+        self.update_current();
+    }
+
+    /// Sets the current entry from the positions of the children, skipping keys deleted in the
+    /// batch. The delta wins ties, as it shadows the base.
+    fn update_current(&mut self) {
+        self.equal_keys = false;
+        loop {
+            let base = self.base_entry();
+            let Some((dk, dt, dv)) = decode_entry(&self.delta) else {
+                self.current = base;
+                self.current_at_base = true;
+                return;
+            };
+            let ord = base.as_ref().map(|(bk, _)| {
+                if self.forward {
+                    self.ucmp.cmp(&dk, bk)
+                } else {
+                    self.ucmp.cmp(bk, &dk)
+                }
+            });
+            if ord == Some(Ordering::Greater) {
+                self.current = base;
+                self.current_at_base = true;
+                return;
+            }
+
+            let equal = ord == Some(Ordering::Equal);
+            match dt {
+                ValueType::TypeValue => {}
+                ValueType::TypeDeletion => {
+                    self.step_delta();
+                    if equal {
+                        self.step_base();
+                    }
+                    continue;
+                }
+                ValueType::TypeBlobIndex => unreachable!("{}", ONLY_PUTS_AND_DELETES),
+            }
+            self.current = Some((dk, dv));
+            self.current_at_base = false;
+            self.equal_keys = equal;
+            return;
+        }
+    }
+
+    /// Steps the child(ren) positioned at the current key.
+    fn step(&mut self) {
+        if self.equal_keys || self.current_at_base {
+            self.step_base();
+        }
+        if self.equal_keys || !self.current_at_base {
+            self.step_delta();
+        }
+        self.update_current();
+    }
+}
+
+impl<I: LdbIterator> LdbIterator for BaseDeltaIterator<I> {
+    fn advance(&mut self) -> bool {
+        if self.current.is_none() {
+            self.forward = true;
+            self.base.reset();
+            self.base.advance();
+            self.delta.reset();
+            self.delta.advance();
+            self.update_current();
+            return self.valid();
+        }
+        if !self.forward {
+            self.change_direction(true);
+        }
+        self.step();
+        self.valid()
+    }
+    fn current(&self, key: &mut Vec<u8>, val: &mut Vec<u8>) -> bool {
+        if let Some((k, v)) = self.current.as_ref() {
+            key.clear();
+            val.clear();
+            key.extend_from_slice(k);
+            val.extend_from_slice(v);
+            true
+        } else {
+            false
+        }
+    }
+    fn seek(&mut self, key: &[u8]) {
+        self.forward = true;
+        self.base.seek(key);
+        self.delta.seek(&seek_key(key, MAX_SEQUENCE_NUMBER));
+        self.update_current();
+    }
+    fn reset(&mut self) {
+        self.base.reset();
+        self.delta.reset();
+        self.forward = true;
+        self.current = None;
+    }
+    fn valid(&self) -> bool {
+        self.current.is_some()
+    }
+    fn prev(&mut self) -> bool {
+        if self.current.is_none() {
+            return false;
+        }
+        if self.forward {
+            self.change_direction(false);
+        }
+        self.step();
+        self.valid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmp::DefaultCmp;
+
+    use std::cell::Cell;
+
+    fn make_batch() -> WriteBatchWithIndex {
+        let mut batch = WriteBatchWithIndex::new(Rc::new(Box::new(DefaultCmp)));
+        batch.put(b"abc", b"1");
+        batch.put(b"abd", b"2");
+        batch.delete(b"abe");
+        batch.put(b"abc", b"3");
+        batch.put(b"abf", b"4");
+        batch.delete(b"abf");
+        batch
+    }
+
+    fn make_base() -> SkipMap {
+        let mut base = SkipMap::new(Rc::new(Box::new(DefaultCmp)));
+        for k in ["aba", "abc", "abe", "abg"] {
+            base.insert(k.as_bytes().to_vec(), b"base".to_vec());
+        }
+        base
+    }
+
+    fn collect<I: LdbIterator>(iter: &mut I) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut v = vec![];
+        while let Some(kv) = iter.next() {
+            v.push(kv);
+        }
+        v
+    }
+
+    #[test]
+    fn test_wbwi_get_from_batch() {
+        let batch = make_batch();
+        assert_eq!(batch.count(), 6);
+        assert_eq!(
+            batch.get_from_batch(b"abc"),
+            BatchLookup::Found(b"3".to_vec())
+        );
+        assert_eq!(
+            batch.get_from_batch(b"abd"),
+            BatchLookup::Found(b"2".to_vec())
+        );
+        assert_eq!(batch.get_from_batch(b"abe"), BatchLookup::Deleted);
+        assert_eq!(batch.get_from_batch(b"abf"), BatchLookup::Deleted);
+        assert_eq!(batch.get_from_batch(b"ab"), BatchLookup::NotFound);
+        assert_eq!(batch.get_from_batch(b"abcc"), BatchLookup::NotFound);
+        assert_eq!(batch.get_from_batch(b"zzz"), BatchLookup::NotFound);
+
+        let empty = WriteBatchWithIndex::new(Rc::new(Box::new(DefaultCmp)));
+        assert!(empty.is_empty());
+        assert_eq!(empty.get_from_batch(b"abc"), BatchLookup::NotFound);
+    }
+
+    #[test]
+    fn test_wbwi_iter_with_base() {
+        let batch = make_batch();
+        let base = make_base();
+        let mut iter = batch.iter_with_base(base.iter());
+
+        assert!(!iter.valid());
+        assert_eq!(
+            collect(&mut iter),
+            vec![
+                (b"aba".to_vec(), b"base".to_vec()),
+                (b"abc".to_vec(), b"3".to_vec()),
+                (b"abd".to_vec(), b"2".to_vec()),
+                (b"abg".to_vec(), b"base".to_vec()),
+            ]
+        );
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_wbwi_iter_with_empty_base() {
+        let batch = make_batch();
+        let base = SkipMap::new(Rc::new(Box::new(DefaultCmp)));
+        let mut iter = batch.iter_with_base(base.iter());
+        assert_eq!(
+            collect(&mut iter),
+            vec![
+                (b"abc".to_vec(), b"3".to_vec()),
+                (b"abd".to_vec(), b"2".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wbwi_iter_seek_prev() {
+        let batch = make_batch();
+        let base = make_base();
+        let mut iter = batch.iter_with_base(base.iter());
+        let (mut k, mut v) = (vec![], vec![]);
+
+        iter.seek(b"abe");
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!((k.as_slice(), v.as_slice()), (&b"abg"[..], &b"base"[..]));
+
+        assert!(iter.prev());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!((k.as_slice(), v.as_slice()), (&b"abd"[..], &b"2"[..]));
+        assert!(iter.prev());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!((k.as_slice(), v.as_slice()), (&b"abc"[..], &b"3"[..]));
+        assert!(iter.prev());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k.as_slice(), b"aba");
+        assert!(!iter.prev());
+        assert!(!iter.valid());
+
+        iter.seek(b"abz");
+        assert!(!iter.valid());
+
+        iter.seek(b"abd");
+        assert!(iter.advance());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k.as_slice(), b"abg");
+        assert!(!iter.advance());
+    }
+
+    /// Counts the seeks on a base iterator.
+    struct SeekCounter<I: LdbIterator>(I, Rc<Cell<usize>>);
+
+    impl<I: LdbIterator> LdbIterator for SeekCounter<I> {
+        fn advance(&mut self) -> bool {
+            self.0.advance()
+        }
+        fn current(&self, key: &mut Vec<u8>, val: &mut Vec<u8>) -> bool {
+            self.0.current(key, val)
+        }
+        fn seek(&mut self, key: &[u8]) {
+            self.1.set(self.1.get() + 1);
+            self.0.seek(key)
+        }
+        fn reset(&mut self) {
+            self.0.reset()
+        }
+        fn valid(&self) -> bool {
+            self.0.valid()
+        }
+        fn prev(&mut self) -> bool {
+            self.0.prev()
+        }
+    }
+
+    #[test]
+    fn test_wbwi_iter_steps_without_seeking() {
+        let batch = make_batch();
+        let base = make_base();
+        let seeks = Rc::new(Cell::new(0));
+        let mut iter = batch.iter_with_base(SeekCounter(base.iter(), seeks.clone()));
+        let (mut k, mut v) = (vec![], vec![]);
+
+        assert_eq!(collect(&mut iter).len(), 4);
+        assert_eq!(seeks.get(), 0);
+
+        iter.seek(b"abg");
+        assert_eq!(seeks.get(), 1);
+        // turning around seeks once; further steps don't.
+        assert!(iter.prev());
+        assert_eq!(seeks.get(), 2);
+        assert!(iter.prev());
+        assert!(iter.prev());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k.as_slice(), b"aba");
+        assert_eq!(seeks.get(), 2);
+
+        assert!(iter.advance());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!((k.as_slice(), v.as_slice()), (&b"abc"[..], &b"3"[..]));
+        assert!(iter.advance());
+        assert!(iter.current(&mut k, &mut v));
+        assert_eq!(k.as_slice(), b"abd");
+        assert_eq!(seeks.get(), 3);
+    }
+
+    #[test]
+    fn test_wbwi_iter_reverse_past_base() {
+        // All keys of the batch are greater than those of the base.
+        let mut batch = WriteBatchWithIndex::new(Rc::new(Box::new(DefaultCmp)));
+        batch.put(b"abx", b"1");
+        batch.put(b"aby", b"2");
+        batch.put(b"abx", b"3");
+        batch.delete(b"abw");
+        batch.put(b"abz", b"4");
+        let base = make_base();
+        let mut iter = batch.iter_with_base(base.iter());
+        let (mut k, mut v) = (vec![], vec![]);
+
+        iter.seek(b"abz");
+        let mut keys = vec![];
+        while iter.current(&mut k, &mut v) {
+            keys.push((k.clone(), v.clone()));
+            iter.prev();
+        }
+        assert_eq!(
+            keys,
+            vec![
+                (b"abz".to_vec(), b"4".to_vec()),
+                (b"aby".to_vec(), b"2".to_vec()),
+                (b"abx".to_vec(), b"3".to_vec()),
+                (b"abg".to_vec(), b"base".to_vec()),
+                (b"abe".to_vec(), b"base".to_vec()),
+                (b"abc".to_vec(), b"base".to_vec()),
+                (b"aba".to_vec(), b"base".to_vec()),
+            ]
+        );
+        assert!(!iter.valid());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::event_listener::EventListener;
+use crate::statistics::{Statistics, Ticker};
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteStallCondition {
+    Normal,
+    Delayed,
+    Stopped,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteStallCause {
+    None,
+    L0FileCount,
+    PendingCompactionBytes,
+}
+
+/// Describes a change of the write stall condition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteStallInfo {
+    pub cause: WriteStallCause,
+    pub prev: WriteStallCondition,
+    pub cur: WriteStallCondition,
+}
+
+/// The thresholds at which writes are slowed down or stopped. A limit of 0 on the pending
+/// compaction bytes disables that limit.
+#[derive(Clone, Debug)]
+pub struct WriteStallLimits {
+    pub level0_slowdown_writes_trigger: usize,
+    pub level0_stop_writes_trigger: usize,
+    pub soft_pending_compaction_bytes_limit: u64,
+    pub hard_pending_compaction_bytes_limit: u64,
+    /// Bytes per second that may be written while writes are delayed.
+    pub delayed_write_rate: u64,
+}
+
+impl Default for WriteStallLimits {
+    fn default() -> Self {
+        WriteStallLimits {
+            level0_slowdown_writes_trigger: 8,
+            level0_stop_writes_trigger: 12,
+            soft_pending_compaction_bytes_limit: 64 << 30,
+            hard_pending_compaction_bytes_limit: 256 << 30,
+            delayed_write_rate: 16 << 20,
+        }
+    }
+}
+
+struct State {
+    condition: WriteStallCondition,
+    cause: WriteStallCause,
+    // While delayed, writes are admitted one after another at the delayed write rate; this is
+    // when the write budget handed out so far runs out.
+    next_write_time: Instant,
+    stopped_writers: usize,
+}
+
+/// WriteController applies backpressure to writers: once the number of L0 files or the bytes
+/// waiting to be compacted pass the soft limits, writes are delayed to the delayed write rate;
+/// past the hard limits, they are stopped until compaction catches up.
+///
+/// Background work reports the current L0 file count and compaction debt via `update()`; the
+/// write path calls `delay_write()` before every write.
+pub struct WriteController {
+    limits: WriteStallLimits,
+    state: Mutex<State>,
+    changed: Condvar,
+    stats: Option<Arc<Statistics>>,
+    listeners: Vec<Arc<dyn EventListener>>,
+}
+
+impl WriteController {
+    pub fn new(
+        limits: WriteStallLimits,
+        stats: Option<Arc<Statistics>>,
+        listeners: Vec<Arc<dyn EventListener>>,
+    ) -> WriteController {
+        assert!(limits.delayed_write_rate > 0);
+        WriteController {
+            limits,
+            state: Mutex::new(State {
+                condition: WriteStallCondition::Normal,
+                cause: WriteStallCause::None,
+                next_write_time: Instant::now(),
+                stopped_writers: 0,
+            }),
+            changed: Condvar::new(),
+            stats,
+            listeners,
+        }
+    }
+
+    fn compute(
+        &self,
+        l0_files: usize,
+        pending_bytes: u64,
+    ) -> (WriteStallCondition, WriteStallCause) {
+        let l = &self.limits;
+        let hard = l.hard_pending_compaction_bytes_limit;
+        let soft = l.soft_pending_compaction_bytes_limit;
+
+        if l0_files >= l.level0_stop_writes_trigger {
+            (WriteStallCondition::Stopped, WriteStallCause::L0FileCount)
+        } else if hard > 0 && pending_bytes >= hard {
+            (
+                WriteStallCondition::Stopped,
+                WriteStallCause::PendingCompactionBytes,
+            )
+        } else if l0_files >= l.level0_slowdown_writes_trigger {
+            (WriteStallCondition::Delayed, WriteStallCause::L0FileCount)
+        } else if soft > 0 && pending_bytes >= soft {
+            (
+                WriteStallCondition::Delayed,
+                WriteStallCause::PendingCompactionBytes,
+            )
+        } else {
+            (WriteStallCondition::Normal, WriteStallCause::None)
+        }
+    }
+
+    /// Recomputes the stall condition from the current number of L0 files and the estimated
+    /// bytes pending compaction, and returns it. Changes are counted in the statistics and
+    /// reported to the event listeners.
+    pub fn update(&self, l0_files: usize, pending_compaction_bytes: u64) -> WriteStallCondition {
+        let (condition, cause) = self.compute(l0_files, pending_compaction_bytes);
+        let info = {
+            let mut state = self.state.lock().unwrap();
+            if state.condition == condition && state.cause == cause {
+                return condition;
+            }
+            let info = WriteStallInfo {
+                cause,
+                prev: state.condition,
+                cur: condition,
+            };
+            state.condition = condition;
+            state.cause = cause;
+            info
+        };
+        self.changed.notify_all();
+
+        if let Some(stats) = self.stats.as_ref() {
+            let ticker = match (condition, cause) {
+                (WriteStallCondition::Delayed, WriteStallCause::L0FileCount) => {
+                    Some(Ticker::StallL0SlowdownCount)
+                }
+                (WriteStallCondition::Stopped, WriteStallCause::L0FileCount) => {
+                    Some(Ticker::StallL0StopCount)
+                }
+                (WriteStallCondition::Delayed, WriteStallCause::PendingCompactionBytes) => {
+                    Some(Ticker::StallPendingCompactionSlowdownCount)
+                }
+                (WriteStallCondition::Stopped, WriteStallCause::PendingCompactionBytes) => {
+                    Some(Ticker::StallPendingCompactionStopCount)
+                }
+                _ => None,
+            };
+            if let Some(t) = ticker {
+                stats.record_tick(t, 1);
+            }
+        }
+        for l in self.listeners.iter() {
+            l.on_stall_conditions_changed(&info);
+        }
+        condition
+    }
+
+    pub fn condition(&self) -> WriteStallCondition {
+        self.state.lock().unwrap().condition
+    }
+
+    /// Returns the number of writers currently blocked in `delay_write()` by stopped writes.
+    pub fn num_stopped_writers(&self) -> usize {
+        self.state.lock().unwrap().stopped_writers
+    }
+
+    fn write_cost(&self, bytes: u64) -> Duration {
+        Duration::from_micros(
+            (bytes as u128 * 1_000_000 / self.limits.delayed_write_rate as u128) as u64,
+        )
+    }
+
+    /// Returns how long a write of `bytes` would be delayed if it was issued now: the time until
+    /// the writes admitted before it have used up their budget, plus its own share. Stopped
+    /// writes can't be expressed as a delay; `delay_write()` blocks them instead.
+    pub fn get_delay(&self, bytes: u64) -> Duration {
+        let state = self.state.lock().unwrap();
+        match state.condition {
+            WriteStallCondition::Delayed => {
+                state
+                    .next_write_time
+                    .saturating_duration_since(Instant::now())
+                    + self.write_cost(bytes)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Delays or blocks a write of `bytes` according to the current condition. Delayed writes of
+    /// all writers are charged against a shared budget, so that together they don't exceed the
+    /// delayed write rate. Time spent waiting is added to `Ticker::StallMicros`.
+    pub fn delay_write(&self, bytes: u64) {
+        let start = Instant::now();
+        let mut stalled = false;
+        let wake_up = {
+            let mut state = self.state.lock().unwrap();
+            if state.condition == WriteStallCondition::Stopped {
+                stalled = true;
+                state.stopped_writers += 1;
+                while state.condition == WriteStallCondition::Stopped {
+                    state = self.changed.wait(state).unwrap();
+                }
+                state.stopped_writers -= 1;
+            }
+            if state.condition == WriteStallCondition::Delayed {
+                let begin = state.next_write_time.max(Instant::now());
+                state.next_write_time = begin + self.write_cost(bytes);
+                Some(state.next_write_time)
+            } else {
+                None
+            }
+        };
+        if let Some(wake_up) = wake_up {
+            stalled = true;
+            thread::sleep(wake_up.saturating_duration_since(Instant::now()));
+        }
+
+        if let (true, Some(stats)) = (stalled, self.stats.as_ref()) {
+            stats.record_tick(Ticker::StallMicros, start.elapsed().as_micros() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> WriteStallLimits {
+        WriteStallLimits {
+            level0_slowdown_writes_trigger: 4,
+            level0_stop_writes_trigger: 8,
+            soft_pending_compaction_bytes_limit: 1000,
+            hard_pending_compaction_bytes_limit: 2000,
+            delayed_write_rate: 1000,
+        }
+    }
+
+    #[derive(Default)]
+    struct StallRecorder(Mutex<Vec<WriteStallInfo>>);
+    impl EventListener for StallRecorder {
+        fn on_stall_conditions_changed(&self, info: &WriteStallInfo) {
+            self.0.lock().unwrap().push(info.clone());
+        }
+    }
+
+    #[test]
+    fn test_write_controller_conditions() {
+        let stats = Arc::new(Statistics::new());
+        let recorder = Arc::new(StallRecorder::default());
+        let wc = WriteController::new(limits(), Some(stats.clone()), vec![recorder.clone()]);
+
+        assert_eq!(wc.update(3, 999), WriteStallCondition::Normal);
+        assert_eq!(wc.update(4, 0), WriteStallCondition::Delayed);
+        assert_eq!(wc.update(5, 0), WriteStallCondition::Delayed);
+        assert_eq!(wc.update(0, 1000), WriteStallCondition::Delayed);
+        assert_eq!(wc.update(8, 0), WriteStallCondition::Stopped);
+        assert_eq!(wc.update(0, 5000), WriteStallCondition::Stopped);
+        assert_eq!(wc.update(0, 0), WriteStallCondition::Normal);
+
+        let changes = recorder.0.lock().unwrap();
+        assert_eq!(changes.len(), 5);
+        assert_eq!(
+            changes[0],
+            WriteStallInfo {
+                cause: WriteStallCause::L0FileCount,
+                prev: WriteStallCondition::Normal,
+                cur: WriteStallCondition::Delayed,
+            }
+        );
+        assert_eq!(changes[1].cause, WriteStallCause::PendingCompactionBytes);
+        assert_eq!(changes[4].cur, WriteStallCondition::Normal);
+
+        assert_eq!(stats.get_ticker_count(Ticker::StallL0SlowdownCount), 1);
+        assert_eq!(stats.get_ticker_count(Ticker::StallL0StopCount), 1);
+        assert_eq!(
+            stats.get_ticker_count(Ticker::StallPendingCompactionSlowdownCount),
+            1
+        );
+        assert_eq!(
+            stats.get_ticker_count(Ticker::StallPendingCompactionStopCount),
+            1
+        );
+    }
+
+    #[test]
+    fn test_write_controller_delay() {
+        let wc = WriteController::new(limits(), None, vec![]);
+        assert_eq!(wc.get_delay(500), Duration::ZERO);
+        wc.update(4, 0);
+        assert_eq!(wc.get_delay(500), Duration::from_millis(500));
+
+        // pending compaction limits of 0 are disabled.
+        let mut l = limits();
+        l.soft_pending_compaction_bytes_limit = 0;
+        l.hard_pending_compaction_bytes_limit = 0;
+        let wc = WriteController::new(l, None, vec![]);
+        assert_eq!(wc.update(0, u64::MAX), WriteStallCondition::Normal);
+    }
+
+    #[test]
+    fn test_write_controller_stopped_writes_resume() {
+        let stats = Arc::new(Statistics::new());
+        let wc = Arc::new(WriteController::new(limits(), Some(stats.clone()), vec![]));
+        wc.update(10, 0);
+
+        let wc2 = wc.clone();
+        let writer = thread::spawn(move || wc2.delay_write(100));
+        while wc.num_stopped_writers() == 0 {
+            thread::yield_now();
+        }
+        assert!(!writer.is_finished());
+
+        wc.update(0, 0);
+        writer.join().unwrap();
+        assert_eq!(wc.num_stopped_writers(), 0);
+        assert!(stats.get_ticker_count(Ticker::StallMicros) > 0);
+    }
+
+    #[test]
+    fn test_write_controller_delayed_writers_share_rate() {
+        let mut l = limits();
+        l.delayed_write_rate = 10_000;
+        let wc = Arc::new(WriteController::new(l, None, vec![]));
+        wc.update(4, 0);
+
+        // 4 writers with 500 bytes each take 50ms on their own, but 200ms together.
+        let start = Instant::now();
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let wc = wc.clone();
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        wc.delay_write(100);
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}